@@ -18,7 +18,7 @@ rustup uninstall $CHANNEL # To avoid old targets
 rustup install --profile minimal $CHANNEL
 # rustup install $CHANNEL
 # rustup component add rust-src rustc-dev llvm-tools --toolchain $CHANNEL
-rustup component add rust-src --toolchain $CHANNEL
+rustup component add rust-src clippy --toolchain $CHANNEL
 RUSTUP_TOOLCHAIN_FOLDER=${CHANNEL}-${TARGET}
 RUSTUP_TOOLCHAIN_PATH=$HOME/.rustup/toolchains/$RUSTUP_TOOLCHAIN_FOLDER
 ls -lha $HOME/.rustup/toolchains
@@ -1,125 +1,605 @@
 use clap::{builder::PossibleValue, ArgAction};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Args {
     pub base_path: Option<String>,
+    pub cache_command: Option<CacheCommand>,
+    pub cache_dir: Option<String>,
+    pub capabilities: Vec<String>,
     pub cargo_output: bool,
+    pub check: bool,
+    pub codegen_flags: Vec<&'static str>,
     pub output_path: Option<String>,
     pub clear_cache: bool,
     pub debug: bool,
+    pub dump_dir: Option<String>,
+    pub emit: String,
+    pub expr: Option<String>,
+    pub extensions: Vec<String>,
     pub gen_pkg_only: bool,
+    pub ide_setup: Option<IdeSetup>,
+    pub inspect: bool,
+    pub jobs: Option<usize>,
+    pub lints: Option<Vec<String>>,
+    pub multimodule: bool,
+    pub new_script: Option<NewScript>,
     pub pkg_path: Option<String>,
-    pub script: Option<String>,
+    pub reflect: Option<String>,
+    pub scripts: Vec<String>,
+    pub serve_socket: Option<String>,
+    pub stage: String,
     pub target: String,
+    pub toolchain: bool,
+    pub validate: bool,
+    pub watch: bool,
+}
+
+/// The kinds of output that `--emit` accepts.
+pub const EMIT_KINDS: &[&str] = &["spv", "spvasm", "wgsl", "glsl", "msl", "rust"];
+
+/**
+The file extension for the given kind of output.
+*/
+pub fn emit_extension(emit: &str) -> &str {
+    match emit {
+        "msl" => "metal",
+        // Not just `rs`, which would overwrite the script.
+        "rust" => "spv.rs",
+        _ => emit,
+    }
+}
+
+/// What `ide-setup` should set up.
+#[derive(Debug)]
+pub struct IdeSetup {
+    pub scripts: Vec<String>,
+    pub output_path: String,
+}
+
+/// The script `new` should create.
+#[derive(Debug)]
+pub struct NewScript {
+    pub path: String,
+    pub stage: String,
+}
+
+/// The lint level arguments of `clippy`, with their short flags as for rustc.
+const LINT_LEVELS: &[(&str, char)] = &[
+    ("allow", 'A'),
+    ("warn", 'W'),
+    ("deny", 'D'),
+    ("forbid", 'F'),
+];
+
+#[derive(Debug)]
+pub enum CacheCommand {
+    List,
+    Size,
+    Prune {
+        max_age: Option<u128>,
+        max_size: Option<u64>,
+    },
+    Clear,
 }
 
 impl Args {
     pub fn parse() -> Self {
-        use clap::{Arg, Command};
-        let cli_version = option_env!("CARGO_PKG_VERSION").unwrap_or("unknown");
-        let rust_gpu_revision = include_str!("rust-gpu-revision.txt");
-        let version = format!(
-            "{cli_version} - https://github.com/EmbarkStudios/rust-gpu/commit/{rust_gpu_revision}"
-        );
-        let about = r#"Compile rust-gpu shader files to spir-v"#;
-
-        let app = Command::new(crate::consts::PROGRAM_NAME)
-            .bin_name(crate::consts::PROGRAM_NAME)
-            .version(version)
-            .about(about)
-            .arg(Arg::new("shader")
-                .index(1)
-                .help("Shader source file to compile")
-                .required_unless_present_any(
-                    ["clear-cache"].iter()
-                )
-                .num_args(1)
-            )
-            .arg(Arg::new("base-path")
-                .help("Base path for resolving dependencies")
-                .short('b')
-                .long("base-path")
-                .num_args(1)
+        let mut command = command();
+        let m = command.get_matches_mut();
+        if let Err(err) = check_shorthand(&command, &m) {
+            command
+                .error(clap::error::ErrorKind::ArgumentConflict, err)
+                .exit();
+        }
+        Self::from_matches(m)
+    }
+
+    fn from_matches(m: clap::ArgMatches) -> Self {
+        // The compile arguments are those of the subcommand, and otherwise those of the top level command given as short for `build`.
+        let (check, c) = match m.subcommand() {
+            Some(("check", c)) | Some(("clippy", c)) => (true, c),
+            Some(("build", c)) | Some(("inspect", c)) => (false, c),
+            _ => (false, &m),
+        };
+        // The arguments only `build` takes keep their defaults from the top level command otherwise.
+        let b = m.subcommand_matches("build").unwrap_or(&m);
+
+        let cache_command = m.subcommand_matches("cache").map(|m| match m.subcommand() {
+            Some(("list", _)) => CacheCommand::List,
+            Some(("size", _)) => CacheCommand::Size,
+            Some(("clear", _)) => CacheCommand::Clear,
+            Some(("prune", m)) => {
+                let max_age = m.get_one::<u128>("max-age").copied();
+                let max_size = m.get_one::<u64>("max-size").copied();
+                CacheCommand::Prune {
+                    max_age: if max_size.is_none() {
+                        max_age.or(Some(crate::consts::MAX_CACHE_AGE_MS))
+                    } else {
+                        max_age
+                    },
+                    max_size,
+                }
+            }
+            _ => unreachable!("cache subcommand is required"),
+        });
+
+        Self {
+            cache_command,
+            check,
+            cache_dir: m.get_one::<String>("cache-dir").map(Into::into),
+            capabilities: c
+                .get_many::<String>("capability")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+            codegen_flags: crate::codegen::FLAGS
+                .iter()
+                .map(|(name, _)| *name)
+                .filter(|name| c.get_flag(name))
+                .collect(),
+            scripts: c
+                .get_many::<String>("shader")
+                .map(|scripts| scripts.cloned().collect())
+                .unwrap_or_default(),
+            serve_socket: m
+                .subcommand_matches("serve")
+                .and_then(|m| m.get_one::<String>("socket"))
+                .map(Into::into),
+            base_path: c.get_one::<String>("base-path").map(Into::into),
+            pkg_path: b.get_one::<String>("pkg_path").map(Into::into),
+            reflect: b.get_one::<String>("reflect").map(Into::into),
+            gen_pkg_only: m.get_flag("gen_pkg_only"),
+            ide_setup: m.subcommand_matches("ide-setup").map(|sub| IdeSetup {
+                scripts: sub.get_many::<String>("shader").unwrap().cloned().collect(),
+                output_path: sub.get_one::<String>("output").cloned().unwrap(),
+            }),
+            inspect: m.subcommand_matches("inspect").is_some(),
+            jobs: c.get_one::<usize>("jobs").copied(),
+            lints: m.subcommand_matches("clippy").map(|sub| {
+                // The lint levels apply in the order given, as for rustc.
+                let mut lints = Vec::new();
+                for (id, flag) in LINT_LEVELS {
+                    if let (Some(indices), Some(values)) =
+                        (sub.indices_of(id), sub.get_many::<String>(id))
+                    {
+                        lints.extend(
+                            indices
+                                .zip(values)
+                                .map(|(idx, lint)| (idx, format!("-{}{}", flag, lint))),
+                        );
+                    }
+                }
+                lints.sort();
+                lints.into_iter().map(|(_, lint)| lint).collect()
+            }),
+            multimodule: b.get_flag("multimodule"),
+            cargo_output: c.get_flag("cargo-output"),
+            new_script: m.subcommand_matches("new").map(|sub| NewScript {
+                path: sub.get_one::<String>("path").cloned().unwrap(),
+                stage: sub.get_one::<String>("stage").cloned().unwrap(),
+            }),
+            output_path: b.get_one::<String>("output").map(Into::into),
+            clear_cache: m.get_flag("clear-cache"),
+            debug: c.get_flag("debug"),
+            dump_dir: b.get_one::<String>("dump-dir").map(Into::into),
+            emit: b.get_one::<String>("emit").map(Into::into).unwrap(),
+            expr: c.get_one::<String>("expr").map(Into::into),
+            extensions: c
+                .get_many::<String>("extension")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+            stage: c.get_one::<String>("stage").map(Into::into).unwrap(),
+            target: c.get_one::<String>("target").map(Into::into).unwrap(),
+            toolchain: m.subcommand_matches("toolchain").is_some(),
+            validate: b.get_flag("validate"),
+            watch: c.get_flag("watch"),
+        }
+    }
+}
+
+impl Args {
+    /// If more than one shader may be built, in which case the output path is a directory.
+    pub fn multiple_scripts(&self) -> bool {
+        self.scripts.len() > 1 || self.scripts.iter().any(|script| is_glob(script))
+    }
+}
+
+// The command line interface, with the arguments of `build` also accepted without the subcommand.
+fn command() -> clap::Command {
+    use clap::{Arg, Command};
+    let cli_version = option_env!("CARGO_PKG_VERSION").unwrap_or("unknown");
+    let rust_gpu_revision = include_str!("rust-gpu-revision.txt");
+    let version = format!(
+        "{cli_version} - https://github.com/EmbarkStudios/rust-gpu/commit/{rust_gpu_revision}"
+    );
+    let about = r#"Compile rust-gpu shader files to spir-v"#;
+
+    Command::new(crate::consts::PROGRAM_NAME)
+        .bin_name(crate::consts::PROGRAM_NAME)
+        .version(version)
+        .about(about)
+        .override_usage(format!(
+            "{name} [OPTIONS] <COMMAND>\n       {name} [BUILD OPTIONS] <SHADER>...",
+            name = crate::consts::PROGRAM_NAME
+        ))
+        .after_help(format!(
+            "Running '{name} <SHADER>...' is short for '{name} build <SHADER>...'.",
+            name = crate::consts::PROGRAM_NAME
+        ))
+        .subcommand_negates_reqs(true)
+        .arg(Arg::new("cache-dir")
+            .help("Directory to cache generated projects and build output in [default: $RUST_GPU_CACHE_DIR, or var/cache/rust-gpu in the install directory if writable, or the user cache directory]")
+            .long("cache-dir")
+            .num_args(1)
+            .global(true)
+        )
+        .subcommand(Command::new("build")
+            .about("Compile shaders to SPIR-V, or to the output given by --emit")
+            .args(compile_args())
+            .args(build_args())
+        )
+        .subcommand(Command::new("cache")
+            .about("Inspect, prune and clear the cache of generated projects and build output")
+            .subcommand_required(true)
+            .subcommand(Command::new("list")
+                .about("List the generated projects in the cache")
             )
-            .arg(Arg::new("cargo-output")
-                .help("Show output from cargo when building")
-                .short('c')
-                .long("cargo-output")
-                .action(ArgAction::SetTrue)
-                .requires("shader")
+            .subcommand(Command::new("size")
+                .about("Show the disk usage of the cache")
             )
-            .arg(Arg::new("debug")
-                .help("Build a debug executable, not an optimised one")
-                .long("debug")
-                .action(ArgAction::SetTrue)
+            .subcommand(Command::new("prune")
+                .about("Remove projects from the cache, least recently used first")
+                .arg(Arg::new("max-age")
+                    .help("Remove projects not used for this long, such as '7d', '12h' or '30m' [default: 7d unless --max-size is given]")
+                    .long("max-age")
+                    .num_args(1)
+                    .value_parser(parse_duration_ms)
+                )
+                .arg(Arg::new("max-size")
+                    .help("Remove projects until the cache fits in this size, such as '10G' or '500M'")
+                    .long("max-size")
+                    .num_args(1)
+                    .value_parser(parse_size)
+                )
             )
-            .arg(Arg::new("clear-cache")
-                .help("Clears out the script cache")
-                .long("clear-cache")
-                .action(ArgAction::SetTrue),
+            .subcommand(Command::new("clear")
+                .about("Remove all generated projects and build output from the cache")
             )
-            .arg(Arg::new("gen_pkg_only")
-                .help("Generate the Cargo package and print the path to it, but don't compile or run it")
-                .long("package")
-                .short('p')
-                .action(ArgAction::SetTrue)
-                .conflicts_with_all(["debug"])
+        )
+        .subcommand(Command::new("check")
+            .about("Check shaders for errors with cargo check, without generating SPIR-V")
+            .args(compile_args())
+        )
+        .subcommand(Command::new("clippy")
+            .about("Lint shaders with clippy, without generating SPIR-V")
+            .args(compile_args())
+            .args(LINT_LEVELS.iter().map(|(id, flag)| {
+                Arg::new(*id)
+                    .help(format!("Set the lint level of a lint or lint group, such as clippy::pedantic, to {}", id))
+                    .long(*id)
+                    .short(*flag)
+                    .num_args(1)
+                    .action(ArgAction::Append)
+                    .value_name("LINT")
+            }))
+        )
+        .subcommand(Command::new("ide-setup")
+            .about("Write a rust-project.json describing shader scripts and their dependencies to rust-analyzer")
+            .arg(Arg::new("shader")
+                .help("Shader source files to describe, which may be glob patterns")
+                .required(true)
+                .num_args(1..)
             )
             .arg(Arg::new("output")
-                .help("Write output to <output>. A file name of '-' represents standard output.")
+                .help("Path to write the project to")
                 .long("output")
                 .short('o')
                 .num_args(1)
-                .conflicts_with_all(["gen_pkg_only"])
+                .default_value("rust-project.json")
             )
-            .arg(Arg::new("target")
-                .help("SPIR-V target")
-                .long("target")
-                .short('t')
+        )
+        .subcommand(Command::new("inspect")
+            .about("Generate the Cargo packages of shaders and show where they are and whether their last build is up to date, without building them")
+            .args(compile_args())
+        )
+        .subcommand(Command::new("new")
+            .about("Create a shader script with an entry point to start from")
+            .arg(Arg::new("path")
+                .help("Path of the script to create")
+                .required(true)
                 .num_args(1)
-                // XXX: https://embarkstudios.github.io/rust-gpu/book/platform-support.html
-                .value_parser([
-                    PossibleValue::new("spirv-unknown-spv1.0"),
-                    PossibleValue::new("spirv-unknown-spv1.1"),
-                    PossibleValue::new("spirv-unknown-spv1.2"),
-                    PossibleValue::new("spirv-unknown-spv1.3"),
-                    PossibleValue::new("spirv-unknown-spv1.4"),
-                    PossibleValue::new("spirv-unknown-spv1.5"),
-                    PossibleValue::new("spirv-unknown-vulkan1.0"),
-                    PossibleValue::new("spirv-unknown-vulkan1.1"),
-                    PossibleValue::new("spirv-unknown-vulkan1.1spv1.4"),
-                    PossibleValue::new("spirv-unknown-vulkan1.2"),
-                    PossibleValue::new("spirv-unknown-webgpu0"),
-                    PossibleValue::new("spirv-unknown-opengl4.0"),
-                    PossibleValue::new("spirv-unknown-opengl4.1"),
-                    PossibleValue::new("spirv-unknown-opengl4.2"),
-                    PossibleValue::new("spirv-unknown-opengl4.3"),
-                    PossibleValue::new("spirv-unknown-opengl4.4"),
-                    PossibleValue::new("spirv-unknown-opengl4.5"),
-                ])
-                .default_value("spirv-unknown-vulkan1.1")
-                .conflicts_with_all(["gen_pkg_only"])
             )
-            .arg(Arg::new("pkg_path")
-                .help("Specify where to place the generated Cargo package")
-                .long("pkg-path")
+            .arg(Arg::new("stage")
+                .help("Shader stage of the entry point")
+                .long("stage")
                 .num_args(1)
-                .requires("shader")
-                .conflicts_with_all(["clear-cache"])
-            );
-
-        let m = app.get_matches();
+                .value_parser(crate::templates::STAGES.to_vec())
+                .default_value("fragment")
+            )
+        )
+        .subcommand(Command::new("serve")
+            .about("Serve compile requests, sent as JSON lines over a Unix socket, keeping state in memory between builds")
+            .arg(Arg::new("socket")
+                .help("Path of the Unix socket to listen on")
+                .long("socket")
+                .num_args(1)
+                .required(true)
+            )
+        )
+        .subcommand(Command::new("toolchain")
+            .about("Show the bundled toolchain and codegen backend, and whether they are installed")
+        )
+        // The arguments of `build` are accepted without the subcommand. They are hidden from the help, which lists the subcommands.
+        .args(compile_args().into_iter().chain(build_args()).map(|arg| arg.hide(true)))
+        .arg(Arg::new("clear-cache")
+            .help("Clears out the script cache, as 'cache clear' does")
+            .long("clear-cache")
+            .action(ArgAction::SetTrue)
+            .hide(true)
+        )
+        .arg(Arg::new("gen_pkg_only")
+            .help("Generate the Cargo package and print the path to it, but don't compile or run it")
+            .long("package")
+            .short('p')
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["debug", "dump-dir", "emit", "multimodule", "output", "reflect", "target", "validate", "watch"])
+            .hide(true)
+        )
+        .mut_arg("shader", |arg| arg.required_unless_present_any(["clear-cache", "expr"]))
+        .mut_arg("pkg_path", |arg| arg.conflicts_with("clear-cache"))
+}
 
-        Self {
-            script: m.get_one::<String>("shader").map(Into::into),
-            base_path: m.get_one::<String>("base-path").map(Into::into),
-            pkg_path: m.get_one::<String>("pkg_path").map(Into::into),
-            gen_pkg_only: m.get_flag("gen_pkg_only"),
-            cargo_output: m.get_flag("cargo-output"),
-            output_path: m.get_one::<String>("output").map(Into::into),
-            clear_cache: m.get_flag("clear-cache"),
-            debug: m.get_flag("debug"),
-            target: m.get_one::<String>("target").map(Into::into).unwrap(),
+// The arguments of the `build` shorthand are not those of a subcommand, which would ignore them, so they can't be given before one.
+fn check_shorthand(command: &clap::Command, m: &clap::ArgMatches) -> Result<(), String> {
+    let Some((name, _)) = m.subcommand() else {
+        return Ok(());
+    };
+    for arg in command.get_arguments().filter(|arg| !arg.is_global_set()) {
+        if m.value_source(arg.get_id().as_str()) == Some(clap::parser::ValueSource::CommandLine) {
+            let arg_name = match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => arg.get_id().to_string(),
+            };
+            return Err(format!(
+                "the argument '{}' cannot be used before the '{}' subcommand",
+                arg_name, name
+            ));
         }
     }
+    Ok(())
+}
+
+/// Parse a duration such as `7d`, `12h`, `30m` or `45s` into milliseconds.
+fn parse_duration_ms(s: &str) -> Result<u128, String> {
+    let (number, unit_ms) = match s.char_indices().last() {
+        Some((idx, 'd')) => (&s[..idx], 24 * 60 * 60 * 1000),
+        Some((idx, 'h')) => (&s[..idx], 60 * 60 * 1000),
+        Some((idx, 'm')) => (&s[..idx], 60 * 1000),
+        Some((idx, 's')) => (&s[..idx], 1000),
+        _ => return Err("expected a duration such as '7d', '12h', '30m' or '45s'".into()),
+    };
+    let number: u128 = number
+        .parse()
+        .map_err(|_| format!("invalid duration: '{}'", s))?;
+    Ok(number * unit_ms)
+}
+
+/// Parse a size such as `10G`, `500M`, `64K` or `1024` into bytes.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (number, unit) = match s.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_alphabetic() => (&s[..idx], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+    let multiplier: u64 = match unit {
+        'B' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        _ => return Err("expected a size such as '10G', '500M', '64K' or '1024'".into()),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size: '{}'", s))?;
+    Ok(number * multiplier)
+}
+
+#[test]
+fn test_parse_duration_and_size() {
+    assert_eq!(Ok(2 * 24 * 60 * 60 * 1000), parse_duration_ms("2d"));
+    assert_eq!(Ok(30 * 60 * 1000), parse_duration_ms("30m"));
+    assert!(parse_duration_ms("30").is_err());
+    assert_eq!(Ok(10 << 30), parse_size("10G"));
+    assert_eq!(Ok(500 << 20), parse_size("500m"));
+    assert_eq!(Ok(1024), parse_size("1024"));
+    assert!(parse_size("10X").is_err());
+}
+
+/// If the given shader argument is a glob pattern to be expanded.
+pub fn is_glob(script: &str) -> bool {
+    script.contains(['*', '?', '['])
+}
+
+// The arguments selecting the shaders and how they are compiled, shared by `build`, `check`, `clippy` and `inspect`.
+fn compile_args() -> Vec<clap::Arg> {
+    use clap::Arg;
+    let mut args = vec![
+        Arg::new("shader")
+            .index(1)
+            .help("Shader source files to compile, which may be glob patterns such as 'shaders/**/*.rs'. A file name of '-' reads the shader from standard input, and a crate directory or Cargo.toml builds an existing shader crate.")
+            .required_unless_present("expr")
+            .num_args(1..),
+        Arg::new("expr")
+            .help("Compile an expression, such as 'vec4(1.0, 0.0, 0.0, 1.0)', wrapped into an entry point for the --stage")
+            .long("expr")
+            .short('e')
+            .num_args(1)
+            .conflicts_with_all(["shader"]),
+        Arg::new("stage")
+            .help("Shader stage of the entry point generated for --expr")
+            .long("stage")
+            .num_args(1)
+            .value_parser(crate::templates::STAGES.to_vec())
+            .default_value("fragment")
+            .requires("expr"),
+        Arg::new("base-path")
+            .help("Base path for resolving dependencies")
+            .short('b')
+            .long("base-path")
+            .num_args(1),
+        Arg::new("cargo-output")
+            .help("Show output from cargo when building")
+            .short('c')
+            .long("cargo-output")
+            .action(ArgAction::SetTrue)
+            .requires("shader"),
+        Arg::new("debug")
+            .help("Build a debug executable, not an optimised one")
+            .long("debug")
+            .action(ArgAction::SetTrue),
+        Arg::new("capability")
+            .help("Enable a SPIR-V capability, such as Int8 (can be given multiple times)")
+            .long("capability")
+            .num_args(1)
+            .action(ArgAction::Append)
+            .value_parser(crate::codegen::parse_capability),
+        Arg::new("extension")
+            .help("Enable a SPIR-V extension, such as SPV_KHR_ray_tracing (can be given multiple times)")
+            .long("extension")
+            .num_args(1)
+            .action(ArgAction::Append)
+            .value_parser(crate::codegen::parse_extension),
+        Arg::new("jobs")
+            .help("Number of shaders to build in parallel [default: number of CPUs]")
+            .long("jobs")
+            .short('j')
+            .num_args(1)
+            .value_parser(clap::value_parser!(usize)),
+        Arg::new("target")
+            .help("SPIR-V target")
+            .long("target")
+            .short('t')
+            .num_args(1)
+            // XXX: https://embarkstudios.github.io/rust-gpu/book/platform-support.html
+            .value_parser([
+                PossibleValue::new("spirv-unknown-spv1.0"),
+                PossibleValue::new("spirv-unknown-spv1.1"),
+                PossibleValue::new("spirv-unknown-spv1.2"),
+                PossibleValue::new("spirv-unknown-spv1.3"),
+                PossibleValue::new("spirv-unknown-spv1.4"),
+                PossibleValue::new("spirv-unknown-spv1.5"),
+                PossibleValue::new("spirv-unknown-vulkan1.0"),
+                PossibleValue::new("spirv-unknown-vulkan1.1"),
+                PossibleValue::new("spirv-unknown-vulkan1.1spv1.4"),
+                PossibleValue::new("spirv-unknown-vulkan1.2"),
+                PossibleValue::new("spirv-unknown-webgpu0"),
+                PossibleValue::new("spirv-unknown-opengl4.0"),
+                PossibleValue::new("spirv-unknown-opengl4.1"),
+                PossibleValue::new("spirv-unknown-opengl4.2"),
+                PossibleValue::new("spirv-unknown-opengl4.3"),
+                PossibleValue::new("spirv-unknown-opengl4.4"),
+                PossibleValue::new("spirv-unknown-opengl4.5"),
+            ])
+            .default_value("spirv-unknown-vulkan1.1"),
+        Arg::new("watch")
+            .help("Rebuild the shader whenever it or its dependencies change")
+            .long("watch")
+            .short('w')
+            .action(ArgAction::SetTrue)
+            .requires("shader"),
+    ];
+    args.extend(crate::codegen::FLAGS.iter().map(|(name, help)| {
+        Arg::new(*name)
+            .help(*help)
+            .long(*name)
+            .action(ArgAction::SetTrue)
+    }));
+    args
+}
+
+// The arguments about what a build writes, which only `build` takes.
+fn build_args() -> Vec<clap::Arg> {
+    use clap::Arg;
+    vec![
+        Arg::new("dump-dir")
+            .help("Rebuild, collecting the codegen backend's dumps of the modules before and after linking and of the SPIR-T passes, along with the build command, into <dump-dir>")
+            .long("dump-dir")
+            .num_args(1),
+        Arg::new("emit")
+            .help("What to write: the SPIR-V module, its disassembly, the module translated to WGSL, GLSL (one file per entry point) or MSL, or Rust source embedding the module for host code")
+            .long("emit")
+            .num_args(1)
+            .value_parser(EMIT_KINDS.to_vec())
+            .default_value("spv"),
+        Arg::new("multimodule")
+            .help("Write one SPIR-V module per entry point, named <output-stem>.<entry>.spv or placed in the <output> directory")
+            .long("multimodule")
+            .action(ArgAction::SetTrue),
+        Arg::new("output")
+            .help("Write output to <output>. A file name of '-' represents standard output. With multiple shaders or glob patterns, <output> is a directory.")
+            .long("output")
+            .short('o')
+            .num_args(1),
+        Arg::new("reflect")
+            .help("Write a JSON description of the entry points and the resources they use to <reflect>, or to standard output for '-'")
+            .long("reflect")
+            .num_args(1),
+        Arg::new("validate")
+            .help("Check the built module against the target environment, such as the capabilities it supports")
+            .long("validate")
+            .action(ArgAction::SetTrue),
+        Arg::new("pkg_path")
+            .help("Specify where to place the generated Cargo package")
+            .long("pkg-path")
+            .num_args(1)
+            .requires("shader"),
+    ]
+}
+
+#[test]
+fn test_command() {
+    command().debug_assert();
+    let parse = |args: &[&str]| Args::from_matches(command().get_matches_from(args));
+
+    let args = parse(&["rust-gpu", "--emit", "wgsl", "shader.rs"]);
+    assert_eq!(args.scripts, ["shader.rs"]);
+    assert_eq!(args.emit, "wgsl");
+    let args = parse(&[
+        "rust-gpu",
+        "--cache-dir",
+        "cache",
+        "build",
+        "--emit",
+        "wgsl",
+        "shader.rs",
+    ]);
+    assert_eq!(args.scripts, ["shader.rs"]);
+    assert_eq!(args.emit, "wgsl");
+    assert_eq!(args.cache_dir.as_deref(), Some("cache"));
+
+    let check = |args: &[&str]| {
+        let command = command();
+        check_shorthand(&command, &command.clone().get_matches_from(args))
+    };
+    assert!(check(&["rust-gpu", "--debug", "shader.rs"]).is_ok());
+    assert!(check(&["rust-gpu", "--cache-dir", "cache", "inspect", "shader.rs"]).is_ok());
+    assert_eq!(
+        check(&[
+            "rust-gpu",
+            "--debug",
+            "-t",
+            "spirv-unknown-vulkan1.2",
+            "inspect",
+            "shader.rs"
+        ]),
+        Err("the argument '--debug' cannot be used before the 'inspect' subcommand".into())
+    );
+
+    let args = parse(&["rust-gpu", "check", "shader.rs"]);
+    assert!(args.check);
+    assert_eq!(args.emit, "spv");
+    assert!(parse(&["rust-gpu", "inspect", "shader.rs"]).inspect);
+    assert!(matches!(
+        parse(&["rust-gpu", "cache", "clear"]).cache_command,
+        Some(CacheCommand::Clear)
+    ));
 }
@@ -1,63 +1,204 @@
 // TODO: Copied from spirv-builder
 pub use rustc_codegen_spirv_types::{CompileResult, ModuleResult};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const ARTIFACT_SUFFIX: &str = ".spv.json";
 
-pub(crate) fn parse_metadata_from_stdout(out: &str) -> Result<PathBuf, SpirvBuilderError> {
+pub(crate) fn get_sole_artifact(out: &str) -> Result<PathBuf, SpirvBuilderError> {
     let last = out
         .lines()
         .filter_map(|line| {
             if let Ok(line) = serde_json::from_str::<RustcOutput>(line) {
                 Some(line)
             } else {
-                // Pass through invalid lines
-                println!("{line}");
+                // Pass through invalid lines, on stderr as stdout may carry a protocol such as
+                // cargo directives in build scripts.
+                eprintln!("{line}");
                 None
             }
         })
         .filter(|line| line.reason == "compiler-artifact")
         .last()
-        .expect("Did not find output file in rustc output");
+        .ok_or(SpirvBuilderError::ArtifactMissing)?;
 
     let mut filenames = last
         .filenames
-        .unwrap()
+        .unwrap_or_default()
         .into_iter()
         .filter(|v| v.ends_with(ARTIFACT_SUFFIX));
-    let filename = filenames.next().unwrap();
-    assert_eq!(
-        filenames.next(),
-        None,
-        "build had multiple `{ARTIFACT_SUFFIX}` artifacts"
-    );
+    let filename = filenames.next().ok_or(SpirvBuilderError::ArtifactMissing)?;
+    if filenames.next().is_some() {
+        return Err(SpirvBuilderError::MultipleArtifacts);
+    }
+
+    Ok(filename.into())
+}
+
+/**
+Returns the rendered compiler diagnostics in the output of cargo, when run with `--message-format=json`.
+*/
+pub(crate) fn diagnostics(out: &str) -> Vec<String> {
+    out.lines().filter_map(diagnostic).collect()
+}
+
+/**
+Returns the rendered compiler diagnostic in a line of the output of cargo, if it is one.
+*/
+pub(crate) fn diagnostic(line: &str) -> Option<String> {
+    let line = serde_json::from_str::<RustcOutput>(line).ok()?;
+    if line.reason != "compiler-message" {
+        return None;
+    }
+    line.message?.rendered
+}
 
-    parse_metadata_file(&filename.into())
+/**
+Returns the library of each proc macro built, by package id, in the output of cargo when run with `--message-format=json`.
+*/
+pub(crate) fn proc_macro_artifacts(out: &str) -> HashMap<String, PathBuf> {
+    out.lines()
+        .filter_map(|line| serde_json::from_str::<RustcOutput>(line).ok())
+        .filter(|line| line.reason == "compiler-artifact")
+        .filter(|line| {
+            line.target
+                .as_ref()
+                .is_some_and(|target| target.kind.iter().any(|kind| kind == "proc-macro"))
+        })
+        .filter_map(|line| {
+            let filename = line.filenames?.into_iter().next()?;
+            Some((line.package_id?, filename.into()))
+        })
+        .collect()
 }
 
 #[derive(Deserialize)]
 struct RustcOutput {
     reason: String,
+    package_id: Option<String>,
+    target: Option<RustcTarget>,
     filenames: Option<Vec<String>>,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcTarget {
+    kind: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    rendered: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum SpirvBuilderError {
+    ArtifactMissing,
+    MultipleArtifacts,
     MetadataFileMissing(std::io::Error),
     MetadataFileMalformed(serde_json::Error),
 }
 
-pub(crate) fn parse_metadata_file(at: &PathBuf) -> Result<PathBuf, SpirvBuilderError> {
+pub(crate) fn parse_metadata_file(at: &PathBuf) -> Result<CompileResult, SpirvBuilderError> {
     let metadata_contents = File::open(at).map_err(SpirvBuilderError::MetadataFileMissing)?;
-    let metadata: CompileResult = serde_json::from_reader(BufReader::new(metadata_contents))
-        .map_err(SpirvBuilderError::MetadataFileMalformed)?;
-    match metadata.module {
-        ModuleResult::SingleModule(spirv_module) => Ok(spirv_module),
-        ModuleResult::MultiModule(_) => {
-            panic!("Multiple modules");
+    serde_json::from_reader(BufReader::new(metadata_contents))
+        .map_err(SpirvBuilderError::MetadataFileMalformed)
+}
+
+/**
+Returns the source files the given artifact was built from, as recorded in the dep-info file next to it.
+*/
+pub(crate) fn leaf_deps(artifact: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let deps_file = artifact.with_extension("d");
+    let deps_map = parse_deps_file(&std::fs::read_to_string(deps_file)?);
+
+    fn recurse(map: &HashMap<String, Vec<String>>, artifact: &str, leaves: &mut Vec<PathBuf>) {
+        match map.get(artifact) {
+            Some(deps) if !deps.is_empty() => {
+                for dep in deps {
+                    recurse(map, dep, leaves);
+                }
+            }
+            _ => leaves.push(artifact.into()),
         }
     }
+
+    let mut leaves = Vec::new();
+    recurse(&deps_map, &artifact.to_string_lossy(), &mut leaves);
+    leaves.sort();
+    leaves.dedup();
+    Ok(leaves)
+}
+
+/**
+Parses a Makefile-style dep-info file, as written by rustc, into a map from target to its dependencies.
+*/
+fn parse_deps_file(content: &str) -> HashMap<String, Vec<String>> {
+    let mut map = HashMap::new();
+    for line in content.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Look for the `: ` separator, taking care not to split on Windows drive letters.
+        let (target, deps) = match line.find(": ") {
+            Some(idx) => (&line[..idx], &line[idx + 2..]),
+            None => match line.strip_suffix(':') {
+                Some(target) => (target, ""),
+                None => continue,
+            },
+        };
+
+        let mut parsed_deps = Vec::new();
+        let mut current = String::new();
+        let mut chars = deps.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(' ') => current.push(' '),
+                    Some(other) => {
+                        current.push('\\');
+                        current.push(other);
+                    }
+                    None => current.push('\\'),
+                },
+                ' ' => {
+                    if !current.is_empty() {
+                        parsed_deps.push(std::mem::take(&mut current));
+                    }
+                }
+                _ => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            parsed_deps.push(current);
+        }
+
+        map.insert(target.replace("\\ ", " "), parsed_deps);
+    }
+    map
+}
+
+#[test]
+fn test_parse_deps_file() {
+    let deps = parse_deps_file(
+        "/target/shader.spv.json: /src/shader.rs /src/my\\ module.rs /target/libspirv_std.rlib\n\
+         \n\
+         /src/shader.rs:\n\
+         /src/my\\ module.rs:\n\
+         \n\
+         # env-dep:RUST_GPU_BASE_PATH=/src\n",
+    );
+    assert_eq!(
+        deps["/target/shader.spv.json"],
+        vec![
+            "/src/shader.rs",
+            "/src/my module.rs",
+            "/target/libspirv_std.rlib"
+        ]
+    );
+    assert_eq!(deps["/src/my module.rs"], Vec::<String>::new());
+    assert_eq!(deps.len(), 3);
 }
@@ -0,0 +1,160 @@
+/*!
+Helper for compiling shaders from the build script of a host crate, in the manner of spirv-builder.
+*/
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::compiler::{CompileOptions, CompileOutput, Compiler};
+use crate::error::{Error, MainResult};
+
+/**
+Start compiling shaders from a build script:
+
+```no_run
+// build.rs
+rust_gpu_driver::build_script()
+    .shader("shaders/foo.rs")
+    .compile()
+    .unwrap();
+```
+
+The host crate can then use the module through the environment variable set for it:
+
+```ignore
+const FOO_SPV: &[u8] = include_bytes!(env!("SHADER_FOO_SPV"));
+```
+*/
+pub fn build_script() -> BuildScript {
+    BuildScript::default()
+}
+
+/**
+Shaders to compile from a build script, see [`build_script`].
+*/
+#[derive(Clone, Debug, Default)]
+pub struct BuildScript {
+    options: CompileOptions,
+    shaders: Vec<PathBuf>,
+}
+
+impl BuildScript {
+    /// The options to compile the shaders with.
+    pub fn options(mut self, options: CompileOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Add a shader to compile, either a script or a crate, relative to the directory of the host crate.
+    pub fn shader(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shaders.push(path.into());
+        self
+    }
+
+    /**
+    Compile the shaders into `OUT_DIR`, and tell cargo about them.
+
+    For a shader `shaders/foo.rs` this prints `cargo:rustc-env=SHADER_FOO_SPV=<path>`, or one `SHADER_FOO_<ENTRY>_SPV` variable per entry point when building with `multimodule`. It also prints `cargo:rerun-if-changed` for the shader and the files it depends on, and a `cargo:warning` for each line of the compiler diagnostics.
+
+    Two shaders with the same name, such as `shaders/a/foo.rs` and `shaders/b/foo.rs`, are an error, as they would set the same variable.
+    */
+    pub fn compile(self) -> Result<Vec<CompileOutput>, Error> {
+        Ok(self.try_compile()?)
+    }
+
+    fn try_compile(self) -> MainResult<Vec<CompileOutput>> {
+        let out_dir = std::env::var_os("OUT_DIR")
+            .ok_or("OUT_DIR is not set, build_script() should be called from a build script")?;
+        let out_dir = PathBuf::from(out_dir);
+        let compiler = Compiler::new(self.options);
+
+        let mut outputs = Vec::new();
+        // The shader each environment variable was set for, as shaders with the same name would overwrite each other.
+        let mut vars = HashMap::new();
+        for shader in &self.shaders {
+            println!("cargo:rerun-if-changed={}", shader.display());
+            let output = compiler.try_compile(shader)?;
+
+            for dependency in &output.dependencies {
+                println!("cargo:rerun-if-changed={}", dependency.display());
+            }
+            for line in output.diagnostics.iter().flat_map(|d| d.lines()) {
+                println!("cargo:warning={}", line);
+            }
+
+            let name = shader_name(shader);
+            for module in &output.modules {
+                let (file_name, var) = match &module.entry_point {
+                    Some(entry) => (
+                        format!("{}.{}.spv", name, entry),
+                        env_var_name(&format!("{}_{}", name, entry)),
+                    ),
+                    None => (format!("{}.spv", name), env_var_name(&name)),
+                };
+                if let Some(other) = vars.insert(var.clone(), shader) {
+                    return Err(format!(
+                        "{} and {} would both set {}, rename one of them",
+                        other.display(),
+                        shader.display(),
+                        var
+                    )
+                    .into());
+                }
+                let path = out_dir.join(file_name);
+                fs::write(&path, &module.spirv)?;
+                println!("cargo:rustc-env={}={}", var, path.display());
+            }
+            outputs.push(output);
+        }
+        Ok(outputs)
+    }
+}
+
+// The name of a shader: the stem of a script, or the directory name of a crate.
+fn shader_name(shader: &Path) -> String {
+    let dir = if shader.file_name() == Some("Cargo.toml".as_ref()) {
+        shader.parent().unwrap_or(shader)
+    } else {
+        shader
+    };
+    let name = if dir.extension().is_some_and(|ext| ext == "rs") {
+        dir.file_stem()
+    } else {
+        dir.file_name()
+    };
+    name.map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "shader".into())
+}
+
+// The environment variable holding the path of the module with the given name, such as `SHADER_FOO_SPV`.
+fn env_var_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("SHADER_{}_SPV", name)
+}
+
+#[test]
+fn test_env_var_name() {
+    assert_eq!(
+        env_var_name(&shader_name(Path::new("shaders/foo.rs"))),
+        "SHADER_FOO_SPV"
+    );
+    assert_eq!(
+        env_var_name(&shader_name(Path::new("shaders/my-crate/Cargo.toml"))),
+        "SHADER_MY_CRATE_SPV"
+    );
+    assert_eq!(
+        env_var_name(&shader_name(Path::new("shaders/my-crate"))),
+        "SHADER_MY_CRATE_SPV"
+    );
+    assert_eq!(env_var_name("foo_main_fs"), "SHADER_FOO_MAIN_FS_SPV");
+}
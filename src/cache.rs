@@ -0,0 +1,181 @@
+/*!
+Inspection and pruning of the cache of generated projects and build output.
+*/
+
+use log::{error, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::MainResult;
+use crate::platform;
+
+/// Name of the file recording when, and for what, a cached project was last used.
+const LAST_USED_FILE: &str = "last-used";
+
+/// A generated project in the cache.
+pub struct CachedProject {
+    pub path: PathBuf,
+    /// The script, or crate manifest, the project was generated for.
+    pub origin: String,
+    /// When the project was last used, in milliseconds since the UNIX epoch.
+    pub last_used: u128,
+    /// Disk usage in bytes.
+    pub size: u64,
+}
+
+/**
+Record that the project in the given cache directory is being used, and for what.
+*/
+pub fn record_use(project_dir: &Path, origin: &str) -> MainResult<()> {
+    fs::create_dir_all(project_dir)?;
+    fs::write(project_dir.join(LAST_USED_FILE), origin)?;
+    Ok(())
+}
+
+/**
+When the project in the given cache directory was last used, in milliseconds since the UNIX epoch.
+
+Falls back to the modification time of the directory for projects without a record of their use.
+*/
+pub fn last_used(project_dir: &fs::DirEntry) -> u128 {
+    fs::metadata(project_dir.path().join(LAST_USED_FILE))
+        .and_then(|md| md.modified())
+        .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis())
+        .unwrap_or_else(|_| platform::dir_last_modified(project_dir))
+}
+
+/**
+Disk usage of a file or directory, in bytes.
+*/
+pub fn disk_usage(path: &Path) -> u64 {
+    let md = match fs::symlink_metadata(path) {
+        Ok(md) => md,
+        Err(_) => return 0,
+    };
+    if !md.is_dir() {
+        return md.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/**
+List the generated projects in the cache, most recently used first.
+*/
+pub fn projects() -> MainResult<Vec<CachedProject>> {
+    let cache_dir = platform::generated_projects_cache_path()?;
+    let entries = match fs::read_dir(&cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut projects = Vec::new();
+    for child in entries {
+        let child = child?;
+        let path = child.path();
+        if path.is_file() {
+            continue;
+        }
+        let origin = fs::read_to_string(path.join(LAST_USED_FILE))
+            .unwrap_or_else(|_| "<unknown>".to_string());
+        projects.push(CachedProject {
+            last_used: last_used(&child),
+            size: disk_usage(&path),
+            origin,
+            path,
+        });
+    }
+    projects.sort_by_key(|p| std::cmp::Reverse(p.last_used));
+    Ok(projects)
+}
+
+/**
+Remove projects from the cache, least recently used first.
+
+Projects unused for longer than `max_age` milliseconds are removed. Then, if the cache is bigger than `max_size` bytes, projects are removed until it fits. If removing all projects isn't enough, the shared build output is removed as well.
+
+Returns the number of bytes freed.
+*/
+pub fn prune(max_age: Option<u128>, max_size: Option<u64>) -> MainResult<u64> {
+    let _lock = crate::lock::cache_exclusive()?;
+    let mut projects = projects()?;
+    let binary_cache_path = platform::binary_cache_path()?;
+    let binary_cache_size = disk_usage(&binary_cache_path);
+    let mut total_size = binary_cache_size + projects.iter().map(|p| p.size).sum::<u64>();
+    let mut freed = 0;
+
+    let cutoff = max_age.map(|max_age| platform::current_time().saturating_sub(max_age));
+
+    // Least recently used projects are at the end.
+    while let Some(project) = projects.last() {
+        let too_old = cutoff.is_some_and(|cutoff| project.last_used <= cutoff);
+        let too_big = max_size.is_some_and(|max_size| total_size > max_size);
+        if !too_old && !too_big {
+            break;
+        }
+
+        let project = projects.pop().unwrap();
+        info!("removing {:?}", project.path);
+        if let Err(err) = fs::remove_dir_all(&project.path) {
+            error!("failed to remove {:?} from cache: {}", project.path, err);
+            continue;
+        }
+        println!("removed {} ({})", project.path.display(), project.origin);
+        total_size -= project.size;
+        freed += project.size;
+    }
+
+    if max_size.is_some_and(|max_size| total_size > max_size) && binary_cache_size > 0 {
+        info!("removing binary cache {:?}", binary_cache_path);
+        fs::remove_dir_all(&binary_cache_path)?;
+        println!("removed {}", binary_cache_path.display());
+        freed += binary_cache_size;
+    }
+
+    Ok(freed)
+}
+
+/**
+Format a number of bytes for humans.
+*/
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/**
+Format how long ago a point in time, in milliseconds since the UNIX epoch, was.
+*/
+pub fn format_age(time: u128) -> String {
+    let seconds = platform::current_time().saturating_sub(time) / 1000;
+    match seconds {
+        0..=59 => format!("{} seconds ago", seconds),
+        60..=3599 => format!("{} minutes ago", seconds / 60),
+        3600..=86399 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+#[test]
+fn test_format_size() {
+    assert_eq!("512 B", format_size(512));
+    assert_eq!("1.5 KiB", format_size(1536));
+    assert_eq!("2.0 GiB", format_size(2 * 1024 * 1024 * 1024));
+}
@@ -0,0 +1,498 @@
+/*!
+The command line interface of the `rust-gpu` binary.
+*/
+
+use log::info;
+
+use crate::arguments::{self, Args};
+use crate::compiler::CompileOptions;
+use crate::defer::Defer;
+use crate::error::{MainError, MainResult};
+use crate::{cache, consts, platform, rustproject, templates, watch};
+use crate::{
+    clean_cache, decide_action_for, generate_package, prepare_action, read_script, Input,
+    InputAction,
+};
+
+/**
+Run the command line interface, exiting the process when done.
+*/
+pub fn main() -> ! {
+    env_logger::init();
+
+    match try_main() {
+        Ok(code) => {
+            std::process::exit(code);
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn try_main() -> MainResult<i32> {
+    let args = arguments::Args::parse();
+    info!("Arguments: {:?}", args);
+
+    if let Some(cache_dir) = &args.cache_dir {
+        platform::set_cache_dir(std::env::current_dir()?.join(cache_dir));
+    }
+
+    if let Some(cache_command) = &args.cache_command {
+        return execute_cache_command(cache_command);
+    }
+
+    if args.toolchain {
+        return execute_toolchain();
+    }
+
+    if let Some(new_script) = &args.new_script {
+        return execute_new(new_script);
+    }
+
+    if let Some(ide_setup) = &args.ide_setup {
+        return execute_ide_setup(ide_setup);
+    }
+
+    if let Some(socket) = &args.serve_socket {
+        #[cfg(unix)]
+        return crate::serve::serve(socket.as_ref());
+        #[cfg(not(unix))]
+        return Err(format!(
+            "cannot listen on {}: serve is only supported on Unix",
+            socket
+        )
+        .into());
+    }
+
+    if args.clear_cache {
+        clean_cache(0)?;
+        if args.scripts.is_empty() && args.expr.is_none() {
+            println!("rust-gpu cache cleared.");
+            return Ok(0);
+        }
+    }
+
+    let scripts = expand_script_globs(&args.scripts)?;
+    if scripts.len() > 1 {
+        if args.watch {
+            return Err("--watch only supports a single shader".into());
+        }
+        if args.pkg_path.is_some() {
+            return Err("--pkg-path only supports a single shader".into());
+        }
+        if args.output_path.as_deref() == Some("-") {
+            return Err("cannot write multiple shaders to standard output".into());
+        }
+    }
+
+    if scripts.iter().filter(|script| *script == "-").count() > 1 {
+        return Err("standard input can only be given once".into());
+    }
+
+    if args.inspect {
+        return inspect_scripts(&scripts, &args);
+    }
+
+    if args.watch {
+        if scripts[0] == "-" {
+            return Err("cannot watch standard input".into());
+        }
+        return watch_script(&scripts[0], &args);
+    }
+
+    let action = if scripts.len() > 1 {
+        None
+    } else {
+        let input = match &args.expr {
+            Some(expr) => Input::Expr(templates::expr_script(expr, &args.stage)?),
+            None => read_script(&scripts[0])?,
+        };
+        info!("input: {:?}", input);
+
+        let action = prepare_action(&input, &args)?;
+
+        if args.gen_pkg_only {
+            println!("{}", action.pkg_path.to_str().unwrap());
+            return Ok(0);
+        }
+        Some(action)
+    };
+
+    // Once we're done, clean out old packages from the cache.
+    let _defer_clear = {
+        Defer::<_, MainError>::new(move || {
+            if args.clear_cache {
+                // Do nothing if cache was cleared explicitly.
+            } else {
+                clean_cache(consts::MAX_CACHE_AGE_MS)?;
+            }
+            Ok(())
+        })
+    };
+
+    match action {
+        Some(action) => {
+            action.execute_command()?;
+            if args.check {
+                eprintln!("no errors found");
+            }
+            Ok(0)
+        }
+        None => build_scripts(&scripts, &args),
+    }
+}
+
+// Execute one of the `cache` subcommands.
+fn execute_cache_command(cache_command: &arguments::CacheCommand) -> MainResult<i32> {
+    match cache_command {
+        arguments::CacheCommand::List => {
+            for project in cache::projects()? {
+                println!(
+                    "{:>16}  {:>10}  {}  {}",
+                    cache::format_age(project.last_used),
+                    cache::format_size(project.size),
+                    project.path.file_name().unwrap().to_string_lossy(),
+                    project.origin
+                );
+            }
+        }
+        arguments::CacheCommand::Size => {
+            let projects_size: u64 = cache::projects()?.iter().map(|p| p.size).sum();
+            let binaries_size = cache::disk_usage(&platform::binary_cache_path()?);
+            println!(
+                "{:>10}  generated projects ({})",
+                cache::format_size(projects_size),
+                platform::generated_projects_cache_path()?.display()
+            );
+            println!(
+                "{:>10}  build output ({})",
+                cache::format_size(binaries_size),
+                platform::binary_cache_path()?.display()
+            );
+            println!(
+                "{:>10}  total",
+                cache::format_size(projects_size + binaries_size)
+            );
+        }
+        arguments::CacheCommand::Prune { max_age, max_size } => {
+            let freed = cache::prune(*max_age, *max_size)?;
+            println!("{} freed", cache::format_size(freed));
+        }
+        arguments::CacheCommand::Clear => {
+            clean_cache(0)?;
+            println!("rust-gpu cache cleared.");
+        }
+    }
+    Ok(0)
+}
+
+/**
+Show where the bundled toolchain and codegen backend are, and whether they are installed.
+
+The exit code is non-zero if something needed to build shaders is missing.
+*/
+fn execute_toolchain() -> MainResult<i32> {
+    let toolchain_path = platform::toolchain_path();
+    let bin_path = toolchain_path.join("bin");
+    let cargo_path = bin_path.join(format!("cargo{}", std::env::consts::EXE_SUFFIX));
+    let clippy_path = bin_path.join(format!("cargo-clippy{}", std::env::consts::EXE_SUFFIX));
+    let backend_path = platform::codegen_backend_path();
+    let missing = |path: &std::path::Path| if path.is_file() { "" } else { " (missing)" };
+
+    println!("install dir:     {}", platform::install_dir().display());
+    println!(
+        "toolchain:       {} {}{}",
+        include_str!("nightly-channel.txt").trim(),
+        toolchain_path.display(),
+        missing(&cargo_path)
+    );
+    println!(
+        "codegen backend: rust-gpu {} {}{}",
+        include_str!("rust-gpu-revision.txt").trim(),
+        backend_path.display(),
+        missing(&backend_path)
+    );
+    println!(
+        "clippy:          {}",
+        if clippy_path.is_file() {
+            "installed"
+        } else {
+            "not installed"
+        }
+    );
+    match platform::cache_dir() {
+        Ok(cache_dir) => println!("cache dir:       {}", cache_dir.display()),
+        Err(err) => println!("cache dir:       {err}"),
+    }
+
+    Ok(if cargo_path.is_file() && backend_path.is_file() {
+        0
+    } else {
+        1
+    })
+}
+
+/**
+Create a script to start from with `new`, refusing to overwrite an existing file.
+*/
+fn execute_new(new_script: &arguments::NewScript) -> MainResult<i32> {
+    let source = templates::new_script(&new_script.stage)?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&new_script.path)
+        .map_err(|e| {
+            MainError::Tag(
+                format!("cannot create {}", new_script.path).into(),
+                Box::new(e.into()),
+            )
+        })?;
+    std::io::Write::write_all(&mut file, source.as_bytes())?;
+    eprintln!("created {}", new_script.path);
+    Ok(0)
+}
+
+/**
+Generate the packages of the scripts, or of `--expr`, and describe them without building.
+*/
+fn inspect_scripts(scripts: &[String], args: &Args) -> MainResult<i32> {
+    if args.watch {
+        return Err("--watch cannot be used with inspect".into());
+    }
+    let inputs = match &args.expr {
+        Some(expr) => vec![Input::Expr(templates::expr_script(expr, &args.stage)?)],
+        None => scripts
+            .iter()
+            .map(|script| read_script(script))
+            .collect::<MainResult<Vec<_>>>()?,
+    };
+    for (idx, input) in inputs.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        let action = prepare_action(input, args)?;
+        print!("{}", action.describe()?);
+    }
+    Ok(0)
+}
+
+/**
+Write a `rust-project.json` for the scripts of `ide-setup`, from the packages generated for them.
+*/
+fn execute_ide_setup(ide_setup: &arguments::IdeSetup) -> MainResult<i32> {
+    let scripts = expand_script_globs(&ide_setup.scripts)?;
+    let mut args = CompileOptions::new().args()?;
+    args.scripts = scripts.clone();
+
+    let mut project = rustproject::RustProject::new(&platform::toolchain_path());
+    for script in &scripts {
+        if script == "-" {
+            return Err("cannot set up standard input for the IDE".into());
+        }
+        let input = read_script(script)?;
+        let action = prepare_action(&input, &args)?;
+        let metadata = action.cargo_metadata()?;
+        let proc_macros =
+            action.build_proc_macros(&rustproject::proc_macro_packages(&metadata)?)?;
+        project.add_package(&metadata, &proc_macros)?;
+    }
+
+    std::fs::write(&ide_setup.output_path, project.to_json()?)?;
+    eprintln!("wrote {}", ide_setup.output_path);
+    Ok(0)
+}
+
+/**
+Expand glob patterns such as `shaders/**/*.rs` among the given scripts.
+
+Arguments without glob metacharacters are passed through as-is. Duplicates are removed.
+*/
+fn expand_script_globs(scripts: &[String]) -> MainResult<Vec<String>> {
+    let mut expanded = Vec::new();
+    for script in scripts {
+        if !arguments::is_glob(script) {
+            expanded.push(script.clone());
+            continue;
+        }
+
+        let paths = glob::glob(script).map_err(|e| format!("invalid glob '{}': {}", script, e))?;
+        let num_before = expanded.len();
+        for path in paths {
+            let path = path.map_err(|e| MainError::Io(e.into()))?;
+            expanded.push(path.to_string_lossy().into_owned());
+        }
+        if expanded.len() == num_before {
+            return Err(format!("no files matched '{}'", script).into());
+        }
+    }
+
+    // Building the same script twice would have both builds share a package.
+    let mut seen = std::collections::HashSet::new();
+    expanded.retain(|script| seen.insert(script.clone()));
+    Ok(expanded)
+}
+
+/**
+Build several scripts, with at most `--jobs` builds running in parallel.
+
+All builds share the binary cache as target directory. A summary of the outcome for each script is printed at the end, and the exit code is non-zero if any build failed.
+*/
+fn build_scripts(scripts: &[String], args: &Args) -> MainResult<i32> {
+    let jobs = args
+        .jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(Into::into))
+        .unwrap_or(1)
+        .clamp(1, scripts.len());
+    info!("building {} scripts using {} jobs", scripts.len(), jobs);
+
+    let inputs: Vec<_> = scripts
+        .iter()
+        .map(|script| read_script(script).map_err(|err| err.to_string()))
+        .collect();
+    // The files in the output directories are named after the scripts, so two scripts with the same name would overwrite each other.
+    if args.output_path.is_some() || args.reflect.is_some() || args.dump_dir.is_some() {
+        let mut names = std::collections::HashMap::new();
+        for (script, input) in scripts.iter().zip(&inputs) {
+            let Ok(input) = input else {
+                continue;
+            };
+            if let Some(other) = names.insert(input.safe_name(), script) {
+                return Err(format!(
+                    "{} and {} would both be written as '{}' in the output directory",
+                    other,
+                    script,
+                    input.safe_name()
+                )
+                .into());
+            }
+        }
+    }
+
+    let next_script = std::sync::atomic::AtomicUsize::new(0);
+    let results = std::sync::Mutex::new(vec![None; scripts.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let idx = next_script.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let Some(input) = inputs.get(idx) else {
+                    break;
+                };
+                let result = match input {
+                    Ok(input) => prepare_action(input, args)
+                        .and_then(|action| {
+                            if args.gen_pkg_only {
+                                println!("{}", action.pkg_path.to_str().unwrap());
+                                return Ok(());
+                            }
+                            action.execute_command().map(|_| ())
+                        })
+                        .map_err(|err| err.to_string()),
+                    Err(err) => Err(err.clone()),
+                };
+                results.lock().unwrap()[idx] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let mut failures = 0;
+    eprintln!();
+    for (script, result) in scripts.iter().zip(results) {
+        match result.expect("all scripts should have been built") {
+            Ok(()) => eprintln!("    ok  {}", script),
+            Err(err) => {
+                failures += 1;
+                eprintln!("FAILED  {}: {}", script, err);
+            }
+        }
+    }
+    eprintln!(
+        "{} shaders {}, {} failed",
+        scripts.len() - failures,
+        if args.check { "checked" } else { "built" },
+        failures
+    );
+
+    Ok(if failures == 0 { 0 } else { 1 })
+}
+
+/**
+Build the script, and then build it again each time it or something it depends on changes.
+
+What is watched is the script itself, the source files listed in the dep-info of the last successful build (which includes files pulled in through `include!`), and the files of local `path` dependencies. Build errors are reported without exiting.
+
+The generated package is kept between builds, and only generated again when its manifest changes.
+*/
+fn watch_script(script: &str, args: &Args) -> MainResult<i32> {
+    let mut action = None;
+    let mut watched_files = Vec::new();
+    loop {
+        let build_result = read_script(script)
+            .and_then(|input| watched_action(&mut action, &input, args))
+            .and_then(|action| action.execute_command());
+        // The cache isn't kept locked while waiting for changes.
+        if let Some(action) = &mut action {
+            action.locks.clear();
+        }
+
+        match build_result {
+            Ok(dependencies) => {
+                watched_files = dependencies;
+                eprintln!("build succeeded, watching for changes...");
+            }
+            Err(err) => {
+                eprintln!("error: {}", err);
+                // Without a successful build there is no dep-info, so the files known up front are watched.
+                if watched_files.is_empty() {
+                    if let Some(action) = &action {
+                        watched_files = action.known_dependencies().unwrap_or_default();
+                        if action.existing_crate {
+                            watched_files.push(action.manifest_path());
+                        }
+                    }
+                    watched_files.push(std::env::current_dir()?.join(script));
+                }
+                eprintln!("build failed, watching for changes...");
+            }
+        }
+
+        watch::wait_for_change(&watched_files);
+    }
+}
+
+/**
+Prepare the action for building the script in watch mode, reusing the package of the previous build unless the manifest or the generated source changed.
+*/
+fn watched_action<'a>(
+    current: &'a mut Option<InputAction>,
+    input: &Input,
+    args: &Args,
+) -> MainResult<&'a InputAction> {
+    let next = decide_action_for(input, args)?;
+    let action = match current.take() {
+        Some(mut action)
+            if action.manifest == next.manifest
+                && action.generated_source == next.generated_source =>
+        {
+            action.lock()?;
+            // The package may have been pruned from the cache since.
+            if !action.existing_crate && !action.manifest_path().is_file() {
+                generate_package(&action)?;
+            }
+            action
+        }
+        _ => {
+            info!("generating the package for {:?}", next.origin);
+            let mut action = next;
+            action.lock()?;
+            if !action.existing_crate {
+                generate_package(&action)?;
+            }
+            action
+        }
+    };
+    Ok(current.insert(action))
+}
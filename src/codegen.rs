@@ -0,0 +1,274 @@
+/*!
+Options for the rust-gpu codegen backend, given on the command line or in the `[package.metadata.rust-gpu]` table of the manifest.
+*/
+
+use rustc_codegen_spirv_types::Capability;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::arguments::Args;
+use crate::error::MainResult;
+
+/**
+Flags changing the semantics of the codegen backend, with their help text.
+
+Each is available as a command line flag and a boolean key in the manifest metadata of the same name, and is passed to the backend as `-Cllvm-args=--<name>`.
+*/
+pub const FLAGS: &[(&str, &str)] = &[
+    (
+        "relax-struct-store",
+        "Allow store from one struct type to a different type with compatible layout and members",
+    ),
+    (
+        "relax-logical-pointer",
+        "Allow allocating an object of a pointer type and returning a pointer value from a function in logical addressing mode",
+    ),
+    (
+        "relax-block-layout",
+        "Enable VK_KHR_relaxed_block_layout when checking standard uniform, storage buffer, and push constant layouts",
+    ),
+    (
+        "uniform-buffer-standard-layout",
+        "Enable VK_KHR_uniform_buffer_standard_layout when checking standard uniform buffer layouts",
+    ),
+    (
+        "scalar-block-layout",
+        "Enable VK_EXT_scalar_block_layout when checking standard uniform, storage buffer, and push constant layouts",
+    ),
+    (
+        "skip-block-layout",
+        "Skip checking standard uniform / storage buffer layout",
+    ),
+    (
+        "preserve-bindings",
+        "Preserve unused descriptor bindings",
+    ),
+];
+
+/**
+The `[package.metadata.rust-gpu]` table of a manifest.
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    pub capabilities: Vec<String>,
+    pub extensions: Vec<String>,
+
+    /// The remaining keys, which should be among the `FLAGS`.
+    #[serde(flatten)]
+    pub flags: BTreeMap<String, toml::Value>,
+}
+
+/**
+Check that the given name is a SPIR-V capability, such as `Int8`.
+*/
+pub fn parse_capability(name: &str) -> Result<String, String> {
+    match Capability::from_str(name) {
+        Ok(_) => Ok(name.to_owned()),
+        Err(()) => Err(format!("unknown SPIR-V capability '{}'", name)),
+    }
+}
+
+/**
+Check that the given name looks like a SPIR-V extension, such as `SPV_KHR_ray_tracing`.
+*/
+pub fn parse_extension(name: &str) -> Result<String, String> {
+    let valid =
+        name.starts_with("SPV_") && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(name.to_owned())
+    } else {
+        Err(format!(
+            "invalid SPIR-V extension '{}', expected a name such as SPV_KHR_ray_tracing",
+            name
+        ))
+    }
+}
+
+/**
+The codegen options for a build, combined from the command line and the manifest.
+*/
+#[derive(Debug, Default)]
+pub struct CodegenOptions {
+    /// SPIR-V capabilities to enable.
+    pub capabilities: Vec<String>,
+
+    /// SPIR-V extensions to enable.
+    pub extensions: Vec<String>,
+
+    /// Names of the enabled `FLAGS`.
+    pub flags: Vec<&'static str>,
+}
+
+impl CodegenOptions {
+    pub fn new(args: &Args, metadata: Metadata) -> MainResult<Self> {
+        let mut options = Self::default();
+        for name in args.capabilities.iter().chain(&metadata.capabilities) {
+            let name = parse_capability(name)?;
+            if !options.capabilities.contains(&name) {
+                options.capabilities.push(name);
+            }
+        }
+        for name in args.extensions.iter().chain(&metadata.extensions) {
+            let name = parse_extension(name)?;
+            if !options.extensions.contains(&name) {
+                options.extensions.push(name);
+            }
+        }
+
+        for (key, value) in &metadata.flags {
+            let Some((name, _)) = FLAGS.iter().find(|(name, _)| name == key) else {
+                return Err(format!("unknown key '{}' in [package.metadata.rust-gpu]", key).into());
+            };
+            let Some(enabled) = value.as_bool() else {
+                return Err(
+                    format!("'{}' in [package.metadata.rust-gpu] must be a boolean", key).into(),
+                );
+            };
+            if enabled && !args.codegen_flags.contains(name) {
+                options.flags.push(name);
+            }
+        }
+        options.flags.extend(&args.codegen_flags);
+        options
+            .flags
+            .sort_by_key(|flag| FLAGS.iter().position(|(name, _)| name == flag));
+
+        Ok(options)
+    }
+
+    /**
+    The arguments to pass to the backend through `-Cllvm-args` for these options.
+    */
+    pub fn llvm_args(&self) -> Vec<String> {
+        self.flags
+            .iter()
+            .map(|name| format!("--{}", name))
+            .collect()
+    }
+
+    /**
+    The `-Ctarget-feature` flag to pass to rustc for the capabilities and extensions, if any.
+    */
+    pub fn target_feature_flag(&self) -> Option<String> {
+        let target_features: Vec<String> = self
+            .capabilities
+            .iter()
+            .map(|name| format!("+{}", name))
+            .chain(self.extensions.iter().map(|name| format!("+ext:{}", name)))
+            .collect();
+        if target_features.is_empty() {
+            None
+        } else {
+            Some(format!("-Ctarget-feature={}", target_features.join(",")))
+        }
+    }
+}
+
+/// Subdirectories of the dump directory, with the backend option dumping into each.
+const DUMPS: &[(&str, &str)] = &[
+    ("pre-link", "--dump-pre-link"),
+    ("post-link", "--dump-post-merge"),
+    ("post-split", "--dump-post-split"),
+    ("spirt-passes", "--dump-spirt-passes"),
+];
+
+/// File in the dump directory the module is written to if the backend panics.
+const DUMP_ON_PANIC_FILE: &str = "module-on-panic.spv";
+
+/// File in the dump directory the build command is written to.
+const DUMP_COMMAND_FILE: &str = "command.sh";
+
+/**
+The backend options dumping modules into the given directory.
+
+The SPIR-T dumps are written both as text and as HTML.
+*/
+pub fn dump_args(dump_dir: &Path) -> Vec<String> {
+    let mut args: Vec<String> = DUMPS
+        .iter()
+        .map(|(subdir, option)| format!("{}={}", option, dump_dir.join(subdir).display()))
+        .collect();
+    args.push(format!(
+        "--dump-module-on-panic={}",
+        dump_dir.join(DUMP_ON_PANIC_FILE).display()
+    ));
+    args
+}
+
+/**
+Remove the dumps of a previous build from the dump directory, and write the command of the coming build to it.
+*/
+pub fn prepare_dump_dir(dump_dir: &Path, cmd: &Command) -> MainResult<()> {
+    for (subdir, _) in DUMPS {
+        let dir = dump_dir.join(subdir);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+    }
+    let panic_dump = dump_dir.join(DUMP_ON_PANIC_FILE);
+    if panic_dump.exists() {
+        fs::remove_file(&panic_dump)?;
+    }
+
+    let mut script = format!(
+        "#!/bin/bash\n# Build command of {} {}, run with a cleared environment.\n",
+        crate::consts::PROGRAM_NAME,
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(dir) = cmd.get_current_dir() {
+        script += &format!("cd {}\n", shell_words::quote(&dir.to_string_lossy()));
+    }
+    script += "env -i";
+    for (key, value) in cmd.get_envs() {
+        let Some(value) = value else {
+            continue;
+        };
+        // The encoded rustflags are separated by the unit separator character.
+        let value = value
+            .to_string_lossy()
+            .split('\x1f')
+            .map(|part| shell_words::quote(part).into_owned())
+            .collect::<Vec<_>>()
+            .join("$'\\x1f'");
+        script += &format!(" \\\n  {}={}", key.to_string_lossy(), value);
+    }
+    script += &format!(
+        " \\\n  {}",
+        shell_words::quote(&cmd.get_program().to_string_lossy())
+    );
+    for arg in cmd.get_args() {
+        script += &format!(" {}", shell_words::quote(&arg.to_string_lossy()));
+    }
+    script.push('\n');
+    fs::write(dump_dir.join(DUMP_COMMAND_FILE), script)?;
+    Ok(())
+}
+
+#[test]
+fn test_codegen_options() {
+    let options = CodegenOptions {
+        capabilities: vec!["Int8".into(), "RuntimeDescriptorArray".into()],
+        extensions: vec!["SPV_KHR_ray_tracing".into()],
+        flags: vec!["relax-block-layout", "preserve-bindings"],
+    };
+    assert_eq!(
+        options.target_feature_flag().as_deref(),
+        Some("-Ctarget-feature=+Int8,+RuntimeDescriptorArray,+ext:SPV_KHR_ray_tracing")
+    );
+    assert_eq!(
+        options.llvm_args(),
+        ["--relax-block-layout", "--preserve-bindings"]
+    );
+    assert!(CodegenOptions::default().target_feature_flag().is_none());
+
+    assert!(parse_capability("Int8").is_ok());
+    assert!(parse_capability("Int9").is_err());
+    assert!(parse_extension("SPV_EXT_descriptor_indexing").is_ok());
+    assert!(parse_extension("ray_tracing").is_err());
+}
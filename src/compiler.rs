@@ -0,0 +1,219 @@
+/*!
+The library API, for building shaders without going through the command line.
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::arguments::Args;
+use crate::build::ModuleResult;
+use crate::error::{Error, MainResult};
+use crate::{codegen, platform, prepare_action, read_script};
+
+/**
+Options for compiling shaders, built up with chained calls:
+
+```no_run
+# use rust_gpu_driver::CompileOptions;
+let options = CompileOptions::new()
+    .target("spirv-unknown-vulkan1.2")
+    .capability("Int64")
+    .validate(true);
+```
+*/
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    target: String,
+    debug: bool,
+    multimodule: bool,
+    capabilities: Vec<String>,
+    extensions: Vec<String>,
+    codegen_flags: Vec<String>,
+    validate: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            target: "spirv-unknown-vulkan1.1".into(),
+            debug: false,
+            multimodule: false,
+            capabilities: Vec::new(),
+            extensions: Vec::new(),
+            codegen_flags: Vec::new(),
+            validate: false,
+        }
+    }
+}
+
+impl CompileOptions {
+    /// The default options, as used by the `rust-gpu` binary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The SPIR-V target, such as `spirv-unknown-vulkan1.2`.
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Build in debug mode.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Build one module per entry point.
+    pub fn multimodule(mut self, multimodule: bool) -> Self {
+        self.multimodule = multimodule;
+        self
+    }
+
+    /// Enable a SPIR-V capability, such as `Int64`.
+    pub fn capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    /// Enable a SPIR-V extension, such as `SPV_KHR_ray_tracing`.
+    pub fn extension(mut self, extension: impl Into<String>) -> Self {
+        self.extensions.push(extension.into());
+        self
+    }
+
+    /// Enable a flag of the codegen backend, such as `relax-block-layout`, as given on the command line without the dashes.
+    pub fn codegen_flag(mut self, flag: impl Into<String>) -> Self {
+        self.codegen_flags.push(flag.into());
+        self
+    }
+
+    /// Check the built modules against the target environment.
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+
+    // The command line arguments these options correspond to.
+    pub(crate) fn args(&self) -> MainResult<Args> {
+        let mut codegen_flags = Vec::new();
+        for flag in &self.codegen_flags {
+            match codegen::FLAGS.iter().find(|(name, _)| name == flag) {
+                Some((name, _)) => codegen_flags.push(*name),
+                None => return Err(format!("unknown codegen flag '{}'", flag).into()),
+            }
+        }
+        Ok(Args {
+            capabilities: self.capabilities.clone(),
+            codegen_flags,
+            debug: self.debug,
+            emit: "spv".into(),
+            extensions: self.extensions.clone(),
+            multimodule: self.multimodule,
+            stage: "fragment".into(),
+            target: self.target.clone(),
+            validate: self.validate,
+            ..Default::default()
+        })
+    }
+}
+
+/**
+A SPIR-V module built by the [`Compiler`].
+*/
+#[derive(Clone, Debug)]
+pub struct CompiledModule {
+    /// The entry point the module is for, when building one module per entry point.
+    pub entry_point: Option<String>,
+
+    /// The SPIR-V module.
+    pub spirv: Vec<u8>,
+}
+
+/**
+The result of compiling a shader.
+*/
+#[derive(Clone, Debug)]
+pub struct CompileOutput {
+    /// The built modules: one, or one per entry point when building with `multimodule`.
+    pub modules: Vec<CompiledModule>,
+
+    /// The names of the entry points.
+    pub entry_points: Vec<String>,
+
+    /// The rendered compiler diagnostics, such as warnings. These are not repeated when the shader was up to date.
+    pub diagnostics: Vec<String>,
+
+    /// The files the build depended on, such as the shader itself and the files of local `path` dependencies.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/**
+Compiles shaders with the bundled toolchain, sharing the cache with the `rust-gpu` binary.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Compiler {
+    options: CompileOptions,
+}
+
+impl Compiler {
+    pub fn new(options: CompileOptions) -> Self {
+        Self { options }
+    }
+
+    /**
+    Compile the shader at the given path, which is either a script or a crate, given by its directory or `Cargo.toml`.
+    */
+    pub fn compile(&self, path: impl AsRef<Path>) -> Result<CompileOutput, Error> {
+        Ok(self.try_compile(path.as_ref())?)
+    }
+
+    pub(crate) fn try_compile(&self, path: &Path) -> MainResult<CompileOutput> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| format!("path is not valid UTF-8: {}", path.display()))?;
+        // Which would otherwise mean standard input.
+        if path == "-" {
+            return Err("cannot compile '-'".into());
+        }
+
+        if !platform::toolchain_path().is_dir() {
+            return Err(format!(
+                "rust-gpu toolchain not found in {}, set RUST_GPU_INSTALL_DIR to the rust-gpu installation",
+                platform::install_dir().display()
+            )
+            .into());
+        }
+
+        let args = self.options.args()?;
+        let input = read_script(path)?;
+        let mut action = prepare_action(&input, &args)?;
+        action.capture_diagnostics = true;
+
+        let build = action.build()?;
+        action.validate(&build.compile_result)?;
+
+        let modules = match &build.compile_result.module {
+            ModuleResult::SingleModule(path) => vec![CompiledModule {
+                entry_point: None,
+                spirv: fs::read(path)?,
+            }],
+            ModuleResult::MultiModule(modules) => modules
+                .iter()
+                .map(|(entry_point, path)| {
+                    Ok(CompiledModule {
+                        entry_point: Some(entry_point.clone()),
+                        spirv: fs::read(path)?,
+                    })
+                })
+                .collect::<MainResult<_>>()?,
+        };
+
+        Ok(CompileOutput {
+            modules,
+            entry_points: build.compile_result.entry_points,
+            diagnostics: build.diagnostics,
+            dependencies: build.dependencies,
+        })
+    }
+}
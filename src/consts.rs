@@ -15,3 +15,17 @@ Measured in milliseconds.
 pub const MAX_CACHE_AGE_MS: u128 = 7 * 24 * 60 * 60 * 1000;
 
 pub const TOOLCHAIN_VERSION: &str = "2023-09-30";
+
+/**
+How often files are checked for changes in watch mode.
+
+Measured in milliseconds.
+*/
+pub const WATCH_POLL_INTERVAL_MS: u64 = 300;
+
+/**
+How many packages the compile server keeps in memory between requests.
+
+The least recently used package is dropped when there are more.
+*/
+pub const SERVE_MAX_PACKAGES: usize = 64;
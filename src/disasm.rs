@@ -0,0 +1,284 @@
+/*!
+Disassembly of SPIR-V modules into the assembly text format, laid out as by `spirv-dis`.
+*/
+
+use rspirv::binary::Disassemble;
+use rspirv::dr::{Instruction, Module, Operand};
+use rspirv::spirv::{Op, Word};
+use std::collections::{HashMap, HashSet};
+
+use crate::error::MainResult;
+
+/// Width of the column holding result ids, which are right-aligned so that the opcodes line up.
+const RESULT_ID_WIDTH: usize = 12;
+
+/**
+Disassemble the given SPIR-V module.
+
+Ids are named after their `OpName` where there is one, and after what they are for types and integer constants. Other ids keep their number.
+*/
+pub fn disassemble(bytes: &[u8]) -> MainResult<String> {
+    let module = rspirv::dr::load_bytes(bytes)
+        .map_err(|e| format!("could not parse SPIR-V module: {}", e))?;
+    let disassembler = Disassembler::new(&module);
+
+    let mut text = String::new();
+    if let Some(header) = &module.header {
+        text += &header.disassemble();
+        text.push('\n');
+    }
+    for inst in module.all_inst_iter() {
+        text += &disassembler.instruction(inst);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/**
+Disassembler for the instructions of a module, also used to show instructions in messages.
+*/
+pub struct Disassembler {
+    names: FriendlyNames,
+    glsl_std_450: HashSet<Word>,
+}
+
+impl Disassembler {
+    pub fn new(module: &Module) -> Self {
+        let glsl_std_450 = module
+            .ext_inst_imports
+            .iter()
+            .filter(|inst| {
+                matches!(inst.operands.first(), Some(Operand::LiteralString(name)) if name == "GLSL.std.450")
+            })
+            .filter_map(|inst| inst.result_id)
+            .collect();
+        Self {
+            names: FriendlyNames::new(module),
+            glsl_std_450,
+        }
+    }
+
+    /// Format a single instruction, using friendly names for ids.
+    pub fn instruction(&self, inst: &Instruction) -> String {
+        let names = &self.names;
+        let mut line = match inst.result_id {
+            Some(id) => format!("{:>width$} = ", names.get(id), width = RESULT_ID_WIDTH),
+            None => " ".repeat(RESULT_ID_WIDTH + 3),
+        };
+        line += "Op";
+        line += inst.class.opname;
+        if let Some(result_type) = inst.result_type {
+            line.push(' ');
+            line += &names.get(result_type);
+        }
+
+        for (idx, operand) in inst.operands.iter().enumerate() {
+            line.push(' ');
+            match operand {
+                Operand::IdRef(id) | Operand::IdScope(id) | Operand::IdMemorySemantics(id) => {
+                    line += &names.get(*id)
+                }
+                // Name extended instructions from GLSL.std.450, such as `Sin`, instead of showing their number.
+                Operand::LiteralExtInstInteger(opcode)
+                    if idx == 1
+                        && matches!(inst.operands[0], Operand::IdRef(set) if self.glsl_std_450.contains(&set)) =>
+                {
+                    match rspirv::grammar::GlslStd450InstructionTable::lookup_opcode(*opcode) {
+                        Some(ext_inst) => line += ext_inst.opname,
+                        None => line += &opcode.to_string(),
+                    }
+                }
+                _ => line += &operand.disassemble(),
+            }
+        }
+        line
+    }
+}
+
+/**
+Names for the ids of a module, such as `%main_fs`, `%v4float` or `%_ptr_Output_v4float`.
+
+Names are made unique by adding a numeric suffix.
+*/
+struct FriendlyNames {
+    names: HashMap<Word, String>,
+    used: HashSet<String>,
+}
+
+impl FriendlyNames {
+    fn new(module: &Module) -> Self {
+        let mut names = Self {
+            names: HashMap::new(),
+            used: HashSet::new(),
+        };
+
+        for inst in &module.debug_names {
+            if let (Op::Name, [Operand::IdRef(target), Operand::LiteralString(name), ..]) =
+                (inst.class.opcode, inst.operands.as_slice())
+            {
+                names.insert(*target, name);
+            }
+        }
+
+        for inst in &module.types_global_values {
+            let Some(id) = inst.result_id else {
+                continue;
+            };
+            if names.names.contains_key(&id) {
+                continue;
+            }
+            if let Some(name) = names.describe(inst) {
+                names.insert(id, &name);
+            }
+        }
+
+        names
+    }
+
+    // Name the given id, unless the name is empty or the id is already named.
+    fn insert(&mut self, id: Word, name: &str) {
+        let mut base: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if base.is_empty() || self.names.contains_key(&id) {
+            return;
+        }
+        // Names can't look like numeric ids.
+        if base.starts_with(|c: char| c.is_ascii_digit()) {
+            base.insert(0, '_');
+        }
+
+        let mut name = base.clone();
+        let mut suffix = 0;
+        while self.used.contains(&name) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        self.used.insert(name.clone());
+        self.names.insert(id, name);
+    }
+
+    // The name of an id without the `%`, if it has one.
+    fn name(&self, id: Word) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+
+    // Describe what a type or constant is, in the style of `spirv-dis`.
+    fn describe(&self, inst: &Instruction) -> Option<String> {
+        let id_name = |idx: usize| match inst.operands.get(idx) {
+            Some(Operand::IdRef(id)) => self.name(*id),
+            _ => None,
+        };
+        let literal = |idx: usize| match inst.operands.get(idx) {
+            Some(Operand::LiteralInt32(value)) => Some(*value),
+            _ => None,
+        };
+
+        match inst.class.opcode {
+            Op::TypeVoid => Some("void".into()),
+            Op::TypeBool => Some("bool".into()),
+            Op::TypeInt => {
+                let width = literal(0)?;
+                let signed = literal(1)? != 0;
+                let base = if signed { "int" } else { "uint" };
+                Some(if width == 32 {
+                    base.into()
+                } else {
+                    format!("{}{}", base, width)
+                })
+            }
+            Op::TypeFloat => Some(match literal(0)? {
+                16 => "half".into(),
+                32 => "float".into(),
+                64 => "double".into(),
+                width => format!("fp{}", width),
+            }),
+            Op::TypeVector => Some(format!("v{}{}", literal(1)?, id_name(0)?)),
+            Op::TypeMatrix => Some(format!("mat{}{}", literal(1)?, id_name(0)?)),
+            Op::TypeRuntimeArray => Some(format!("_runtimearr_{}", id_name(0)?)),
+            Op::TypePointer => match inst.operands.first() {
+                Some(Operand::StorageClass(storage_class)) => {
+                    Some(format!("_ptr_{:?}_{}", storage_class, id_name(1)?))
+                }
+                _ => None,
+            },
+            Op::ConstantTrue => Some("true".into()),
+            Op::ConstantFalse => Some("false".into()),
+            Op::Constant => {
+                let type_name = self.name(inst.result_type?)?;
+                let is_int = type_name.starts_with("int") || type_name.starts_with("uint");
+                match inst.operands.first() {
+                    Some(Operand::LiteralInt32(value)) if is_int => {
+                        let value = if type_name.starts_with("int") {
+                            (*value as i32).to_string().replace('-', "n")
+                        } else {
+                            value.to_string()
+                        };
+                        Some(format!("{}_{}", type_name, value))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // The name of an id as written in the disassembly, including the `%`.
+    fn get(&self, id: Word) -> String {
+        match self.name(id) {
+            Some(name) => format!("%{}", name),
+            None => format!("%{}", id),
+        }
+    }
+}
+
+#[test]
+fn test_disassemble() {
+    use crate::test_util::{assemble_with_entry_point, shader_builder};
+    use rspirv::spirv::{ExecutionModel, StorageClass};
+
+    let mut b = shader_builder();
+    let float = b.type_float(32);
+    let v4float = b.type_vector(float, 4);
+    let output_ptr = b.type_pointer(None, StorageClass::Output, v4float);
+    let output = b.variable(output_ptr, None, StorageClass::Output, None);
+    b.name(output, "output");
+    let bytes = assemble_with_entry_point(
+        b,
+        ExecutionModel::Fragment,
+        "main_fs",
+        &[output],
+        |b, main| {
+            b.name(main, "main_fs");
+        },
+    );
+
+    let text = disassemble(&bytes).unwrap();
+    let lines: Vec<&str> = text
+        .lines()
+        .skip_while(|line| line.starts_with(';'))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "               OpCapability Shader",
+            "               OpMemoryModel Logical GLSL450",
+            "               OpEntryPoint Fragment %main_fs \"main_fs\" %output",
+            "               OpName %output \"output\"",
+            "               OpName %main_fs \"main_fs\"",
+            "      %float = OpTypeFloat 32",
+            "    %v4float = OpTypeVector %float 4",
+            "%_ptr_Output_v4float = OpTypePointer Output %v4float",
+            "     %output = OpVariable %_ptr_Output_v4float Output",
+            "       %void = OpTypeVoid",
+            "          %6 = OpTypeFunction %void",
+            "    %main_fs = OpFunction %void None %6",
+            "          %8 = OpLabel",
+            "               OpReturn",
+            "               OpFunctionEnd",
+        ]
+    );
+
+    assert!(disassemble(b"not spir-v").is_err());
+}
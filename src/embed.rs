@@ -0,0 +1,133 @@
+/*!
+Generation of Rust source embedding a built module, for host code to `include!`.
+*/
+
+use std::collections::{BTreeSet, HashSet};
+
+use crate::error::MainResult;
+use crate::reflect;
+
+// An upper case identifier for the given name, made unique among the `used` ones.
+fn const_name(name: &str, used: &mut HashSet<String>) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    while !used.insert(ident.clone()) {
+        ident.push('_');
+    }
+    ident
+}
+
+/**
+Rust source with the given SPIR-V module as a `SPIRV` constant, and constants for its entry points and descriptor bindings.
+
+The entry points reported by the build are checked against those declared in the module.
+*/
+pub fn rust_source(spirv: &[u8], entry_points: &[String], origin: &str) -> MainResult<String> {
+    if spirv.len() % 4 != 0 {
+        return Err("SPIR-V module size is not a multiple of 4 bytes".into());
+    }
+    let reflection = reflect::reflect(&[spirv.to_vec()])?;
+    for entry_point in entry_points {
+        if !reflection
+            .entry_points
+            .iter()
+            .any(|e| &e.name == entry_point)
+        {
+            return Err(format!(
+                "entry point '{}' is missing from the built module",
+                entry_point
+            )
+            .into());
+        }
+    }
+
+    let mut source = format!(
+        "// Generated by {} {} from {}, do not edit.\n",
+        crate::consts::PROGRAM_NAME,
+        env!("CARGO_PKG_VERSION"),
+        origin
+    );
+
+    // Host crates are unlikely to use every constant.
+    source += "\n/// The SPIR-V module.\n#[allow(dead_code)]\npub const SPIRV: &[u32] = &[";
+    for (idx, word) in spirv.chunks_exact(4).enumerate() {
+        if idx % 8 == 0 {
+            source += "\n   ";
+        }
+        let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        source += &format!(" 0x{:08x},", word);
+    }
+    source += "\n];\n";
+
+    source += "\n/// Names of the entry points.\n#[allow(dead_code)]\npub mod entry_points {\n";
+    let mut used = HashSet::from(["ALL".to_owned()]);
+    let mut consts = Vec::new();
+    for entry_point in entry_points {
+        let name = const_name(entry_point, &mut used);
+        source += &format!("    pub const {}: &str = {:?};\n", name, entry_point);
+        consts.push(name);
+    }
+    source += &format!(
+        "    pub const ALL: &[&str] = &[{}];\n}}\n",
+        consts.join(", ")
+    );
+
+    let bindings: BTreeSet<(u32, u32, Option<String>)> = reflection
+        .entry_points
+        .iter()
+        .flat_map(|entry_point| &entry_point.bindings)
+        .map(|binding| (binding.set, binding.binding, binding.name.clone()))
+        .collect();
+    if !bindings.is_empty() {
+        source += "\n/// Descriptor sets and bindings of the resources.\n#[allow(dead_code)]\npub mod bindings {\n";
+        let mut used = HashSet::new();
+        for (set, binding, name) in bindings {
+            let name = name.unwrap_or_else(|| format!("set{}_binding{}", set, binding));
+            let prefix = const_name(&name, &mut used);
+            source += &format!("    pub const {}_SET: u32 = {};\n", prefix, set);
+            source += &format!("    pub const {}_BINDING: u32 = {};\n", prefix, binding);
+        }
+        source += "}\n";
+    }
+
+    Ok(source)
+}
+
+#[test]
+fn test_rust_source() {
+    use crate::test_util::{assemble_with_entry_point, shader_builder};
+    use rspirv::spirv::ExecutionModel;
+
+    let bytes = assemble_with_entry_point(
+        shader_builder(),
+        ExecutionModel::Fragment,
+        "main_fs",
+        &[],
+        |_, _| {},
+    );
+
+    let source = rust_source(&bytes, &["main_fs".to_owned()], "shader.rs").unwrap();
+    assert!(source.contains("#[allow(dead_code)]\npub const SPIRV: &[u32] = &[\n    0x07230203,"));
+    assert!(source.contains(
+        "    pub const MAIN_FS: &str = \"main_fs\";\n    pub const ALL: &[&str] = &[MAIN_FS];\n"
+    ));
+    assert!(!source.contains("mod bindings"));
+
+    assert!(rust_source(&bytes, &["main_vs".to_owned()], "shader.rs").is_err());
+
+    let mut used = HashSet::new();
+    assert_eq!(const_name("main_fs", &mut used), "MAIN_FS");
+    assert_eq!(const_name("main-fs", &mut used), "MAIN_FS_");
+    assert_eq!(const_name("2d", &mut used), "_2D");
+}
@@ -1,9 +1,9 @@
 /*!
-Definition of the program's main error type.
+Definition of the program's main error type, and of the error type of the library API.
 */
 
 use std::borrow::Cow;
-use std::error::Error;
+use std::error;
 use std::fmt;
 use std::io;
 use std::result::Result;
@@ -15,8 +15,10 @@ pub type MainResult<T> = Result<T, MainError>;
 #[derive(Debug)]
 pub enum MainError {
     Io(io::Error),
+    /// The build failed, with the rendered compiler diagnostics if they were captured.
+    Build(Vec<String>),
     Tag(Cow<'static, str>, Box<MainError>),
-    Other(Box<dyn Error>),
+    Other(Box<dyn error::Error>),
     OtherOwned(String),
     OtherBorrowed(&'static str),
 }
@@ -27,6 +29,8 @@ impl fmt::Display for MainError {
         use std::fmt::Display;
         match self {
             Io(err) => Display::fmt(err, fmt),
+            Build(diagnostics) if diagnostics.is_empty() => write!(fmt, "build failed"),
+            Build(diagnostics) => write!(fmt, "build failed:\n{}", diagnostics.concat()),
             Tag(msg, ref err) => write!(fmt, "{}: {}", msg, err),
             Other(err) => Display::fmt(err, fmt),
             OtherOwned(err) => Display::fmt(err, fmt),
@@ -35,7 +39,7 @@ impl fmt::Display for MainError {
     }
 }
 
-impl Error for MainError {}
+impl error::Error for MainError {}
 
 macro_rules! from_impl {
     ($src_ty:ty => $dst_ty:ty, $src:ident -> $e:expr) => {
@@ -53,9 +57,56 @@ from_impl! { &'static str => MainError, v -> MainError::OtherBorrowed(v) }
 
 impl<T> From<Box<T>> for MainError
 where
-    T: 'static + Error,
+    T: 'static + error::Error,
 {
     fn from(src: Box<T>) -> Self {
         Self::Other(src)
     }
 }
+
+/**
+An error from compiling a shader through the library API.
+*/
+#[derive(Debug)]
+pub struct Error {
+    message: String,
+    diagnostics: Vec<String>,
+}
+
+impl Error {
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The rendered compiler diagnostics, such as type errors, if the shader failed to build.
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{}", self.message)?;
+        if !self.diagnostics.is_empty() {
+            write!(fmt, ":\n{}", self.diagnostics.concat())?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<MainError> for Error {
+    fn from(err: MainError) -> Self {
+        match err {
+            MainError::Build(diagnostics) => Self {
+                message: "build failed".into(),
+                diagnostics,
+            },
+            err => Self {
+                message: err.to_string(),
+                diagnostics: Vec::new(),
+            },
+        }
+    }
+}
@@ -0,0 +1,107 @@
+/*!
+Fingerprints of builds, used to skip invoking cargo when nothing affecting the output has changed.
+*/
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::build::{CompileResult, ModuleResult};
+use crate::error::MainResult;
+
+/// What is recorded about the last build of a package.
+#[derive(Deserialize, Serialize)]
+pub struct BuildRecord {
+    /// Fingerprint of the settings and dependencies of the build.
+    pub fingerprint: String,
+    /// The files the build depended on.
+    pub dependencies: Vec<PathBuf>,
+    /// The metadata of the build, pointing at the built modules.
+    pub compile_result: CompileResult,
+}
+
+/**
+Compute the fingerprint of a build from its settings and the files it depends on.
+
+Source files are fingerprinted by their contents. Compiled libraries, which may be big and are only ever replaced as a whole, are fingerprinted by their size and modification time.
+*/
+pub fn compute(settings: &str, dependencies: &[PathBuf]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(settings.as_bytes());
+
+    for dependency in dependencies {
+        hasher.update(dependency.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+
+        let is_library = dependency.extension().is_some_and(|ext| {
+            ["rlib", "rmeta", "so", "dylib", "dll"]
+                .iter()
+                .any(|e| ext == *e)
+        });
+        let contents = if is_library {
+            fs::metadata(dependency)
+                .map(|md| format!("{}:{:?}", md.len(), md.modified().ok()).into_bytes())
+        } else {
+            fs::read(dependency)
+        };
+        match contents {
+            Ok(contents) => hasher.update(&contents),
+            Err(_) => hasher.update(b"<missing>"),
+        }
+        hasher.update(b"\0");
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/**
+Load the record of the last build, if there is one.
+*/
+pub fn load(path: &Path) -> Option<BuildRecord> {
+    let contents = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(record) => Some(record),
+        Err(err) => {
+            debug!("ignoring malformed build record {:?}: {}", path, err);
+            None
+        }
+    }
+}
+
+/**
+Store the record of a build.
+*/
+pub fn store(path: &Path, record: &BuildRecord) -> MainResult<()> {
+    let dir = path.parent().ok_or("The given path should be a file")?;
+    fs::create_dir_all(dir)?;
+    let contents = serde_json::to_string_pretty(record).map_err(|e| e.to_string())?;
+    crate::overwrite_file(path, &contents)
+}
+
+/**
+Check that the modules built by a previous build are still around.
+*/
+pub fn artifacts_exist(compile_result: &CompileResult) -> bool {
+    match &compile_result.module {
+        ModuleResult::SingleModule(path) => path.is_file(),
+        ModuleResult::MultiModule(modules) => modules.values().all(|path| path.is_file()),
+    }
+}
+
+#[test]
+fn test_compute() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let source = dir.path().join("shader.rs");
+    let missing = dir.path().join("missing.rs");
+    fs::write(&source, "fn a() {}").unwrap();
+
+    let dependencies = vec![source.clone(), missing];
+    let fingerprint = compute("target=a", &dependencies);
+    assert_eq!(fingerprint, compute("target=a", &dependencies));
+    assert_ne!(fingerprint, compute("target=b", &dependencies));
+
+    fs::write(&source, "fn b() {}").unwrap();
+    assert_ne!(fingerprint, compute("target=a", &dependencies));
+}
@@ -0,0 +1,1413 @@
+/*!
+Driver for [rust-gpu](https://github.com/EmbarkStudios/rust-gpu), building shaders written in Rust to SPIR-V using a bundled toolchain.
+
+Besides the `rust-gpu` binary, the driver can be embedded through the [`Compiler`] API:
+
+```no_run
+use rust_gpu_driver::{CompileOptions, Compiler};
+
+let compiler = Compiler::new(CompileOptions::new().target("spirv-unknown-vulkan1.2"));
+let output = compiler.compile("shaders/fragment.rs")?;
+for module in &output.modules {
+    println!("{:?}: {} bytes", module.entry_point, module.spirv.len());
+}
+# Ok::<(), rust_gpu_driver::Error>(())
+```
+*/
+
+#![forbid(unsafe_code)]
+
+mod arguments;
+mod build;
+mod build_script;
+mod cache;
+#[doc(hidden)]
+pub mod cli;
+mod codegen;
+mod compiler;
+mod consts;
+mod defer;
+mod disasm;
+mod embed;
+mod error;
+mod fingerprint;
+mod lock;
+mod manifest;
+mod platform;
+mod reflect;
+mod rustproject;
+#[cfg(unix)]
+mod serve;
+mod templates;
+#[cfg(test)]
+mod test_util;
+mod translate;
+mod validate;
+mod watch;
+
+pub use build_script::{build_script, BuildScript};
+pub use compiler::{CompileOptions, CompileOutput, CompiledModule, Compiler};
+pub use error::Error;
+
+use arguments::Args;
+use log::{debug, error, info};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::build::{CompileResult, ModuleResult};
+use crate::defer::Defer;
+use crate::error::{MainError, MainResult};
+
+use sha1::{Digest, Sha1};
+
+// Read the script at the given path into an input, where '-' means standard input.
+fn read_script(script: &str) -> MainResult<Input> {
+    if script == "-" {
+        let mut body = String::new();
+        std::io::stdin().read_to_string(&mut body)?;
+        return Ok(Input::Stdin(body));
+    }
+
+    // A crate is given by its directory or its manifest.
+    let script_path = Path::new(script);
+    let crate_manifest_path = if script_path.is_dir() {
+        Some(script_path.join("Cargo.toml"))
+    } else if script_path.file_name() == Some("Cargo.toml".as_ref()) {
+        Some(script_path.to_path_buf())
+    } else {
+        None
+    };
+    if let Some(manifest_path) = crate_manifest_path {
+        let manifest_path = std::env::current_dir()?.join(manifest_path);
+        let (name, manifest) = manifest::read_crate_manifest(&manifest_path)?;
+        return Ok(Input::Crate(name, manifest_path, manifest));
+    }
+
+    let (path, mut file) =
+        find_script(script.as_ref()).ok_or(format!("cannot open input file: '{}'", script))?;
+
+    let script_name = path
+        .file_stem()
+        .map(|os| os.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "unknown".into());
+
+    let mut body = String::new();
+    file.read_to_string(&mut body)?;
+
+    let script_path = std::env::current_dir()?.join(path);
+
+    Ok(Input::File(script_name, script_path, body))
+}
+
+/**
+Decide what to do with the input and generate the package for it.
+
+The returned action holds the locks needed to build it, so that the package isn't touched by other invocations until it is dropped.
+*/
+fn prepare_action(input: &Input, args: &Args) -> MainResult<InputAction> {
+    let mut action = decide_action_for(input, args)?;
+    info!("action: {:?}", action);
+
+    action.lock()?;
+
+    if !action.existing_crate {
+        generate_package(&action)?;
+    }
+
+    Ok(action)
+}
+
+/**
+Clean up the cache folder.
+
+Looks for all folders whose metadata says they were created at least `max_age` in the past and kills them dead.
+*/
+fn clean_cache(max_age: u128) -> MainResult<()> {
+    info!("cleaning cache with max_age: {:?}", max_age);
+
+    // Clearing the cache waits for builds using it, while the periodic cleanup is skipped if the cache is in use.
+    let _lock = if max_age == 0 {
+        lock::cache_exclusive()?
+    } else {
+        match lock::try_cache_exclusive()? {
+            Some(lock) => lock,
+            None => {
+                info!("cache is in use, skipping cleanup");
+                return Ok(());
+            }
+        }
+    };
+
+    if max_age == 0 {
+        info!("max_age is 0, clearing binary cache...");
+        let cache_dir = platform::binary_cache_path()?;
+        if let Err(err) = fs::remove_dir_all(&cache_dir) {
+            error!("failed to remove binary cache {:?}: {}", cache_dir, err);
+        }
+    }
+
+    let cutoff = platform::current_time() - max_age;
+    info!("cutoff:     {:>20?} ms", cutoff);
+
+    let cache_dir = platform::generated_projects_cache_path()?;
+    for child in fs::read_dir(cache_dir)? {
+        let child = child?;
+        let path = child.path();
+        if path.is_file() {
+            continue;
+        }
+
+        info!("checking: {:?}", path);
+
+        let remove_dir = || {
+            let meta_mtime = cache::last_used(&child);
+            info!("meta_mtime: {:>20?} ms", meta_mtime);
+
+            meta_mtime <= cutoff
+        };
+
+        if remove_dir() {
+            info!("removing {:?}", path);
+            if let Err(err) = fs::remove_dir_all(&path) {
+                error!("failed to remove {:?} from cache: {}", path, err);
+            }
+        }
+    }
+    info!("done cleaning cache.");
+    Ok(())
+}
+
+// Generate a package from the input.
+fn generate_package(action: &InputAction) -> MainResult<()> {
+    info!("creating pkg dir...");
+    fs::create_dir_all(&action.pkg_path)?;
+    let cleanup_dir: Defer<_, MainError> = Defer::new(|| {
+        if action.using_cache {
+            // Only cleanup on failure if we are using the shared package
+            // cache, and not when the user has specified the package path
+            // (since that would risk removing user files).
+            info!("cleaning up cache directory {:?}", &action.pkg_path);
+            fs::remove_dir_all(&action.pkg_path)?;
+        }
+        Ok(())
+    });
+
+    info!("generating Cargo package...");
+    let mani_path = action.manifest_path();
+
+    overwrite_file(&mani_path, &action.manifest)?;
+
+    if let Some((source_path, content)) = &action.generated_source {
+        overwrite_file(&action.pkg_path.join(source_path), content)?;
+    }
+
+    info!("disarming pkg dir cleanup...");
+    cleanup_dir.disarm();
+
+    Ok(())
+}
+
+/**
+This represents what to do with the input provided by the user.
+*/
+#[derive(Debug)]
+struct InputAction {
+    /// Always show cargo output?
+    cargo_output: bool,
+
+    /// Capture the compiler diagnostics instead of having cargo print them?
+    capture_diagnostics: bool,
+
+    /// Where to send each captured diagnostic as soon as the compiler emits it.
+    diagnostics_sender: Option<std::sync::mpsc::Sender<String>>,
+
+    /// Directory where the package should live.
+    pkg_path: PathBuf,
+
+    /// Name of the package.
+    pkg_name: String,
+
+    /// Where the fingerprint of the last build is recorded.
+    fingerprint_path: PathBuf,
+
+    /// Description of where the input came from, for listing the cache.
+    origin: String,
+
+    /// Path of the script, if the input is a script file.
+    script_path: Option<PathBuf>,
+
+    /// Is the package an existing crate, rather than one generated from the input?
+    existing_crate: bool,
+
+    /// Script source to write into the package, and where, for inputs which aren't script files.
+    generated_source: Option<(PathBuf, String)>,
+
+    /**
+    Is the package directory in the cache?
+
+    Currently, this can be inferred from `emit_metadata`, but there's no *intrinsic* reason they should be tied together.
+    */
+    using_cache: bool,
+
+    /// If script should be built in debug mode.
+    debug: bool,
+
+    /// What to write to the output path, as given by `--emit`.
+    emit: String,
+
+    /// Directory to collect the dumps of the codegen backend in.
+    dump_dir: Option<PathBuf>,
+
+    /// Environment variables available at compilation time of the script.
+    env: Vec<(&'static str, OsString)>,
+
+    /// The package manifest contents.
+    manifest: String,
+
+    /// If one SPIR-V module should be emitted per entry point.
+    multimodule: bool,
+
+    // Path to write the output to, with `-` meaning standard output
+    spirv_output_path: String,
+
+    /// Path to write the reflection JSON to, with `-` meaning standard output.
+    reflect_path: Option<String>,
+
+    // The rust-gpu spir-v targert
+    target: String,
+
+    /// If the built modules should be validated for the target.
+    validate: bool,
+
+    /// Only check the package for errors, without generating SPIR-V.
+    check: bool,
+
+    /// Lint the package with clippy and these lint arguments, such as `-Wclippy::pedantic`, when checking it.
+    lints: Option<Vec<String>>,
+
+    /// Options for the codegen backend.
+    codegen: codegen::CodegenOptions,
+
+    /// Locks held while the action is alive.
+    locks: Vec<lock::FileLock>,
+}
+
+impl InputAction {
+    fn manifest_path(&self) -> PathBuf {
+        self.pkg_path.join("Cargo.toml")
+    }
+
+    /**
+    Take the locks needed to build the package, which are held until they are cleared or the action is dropped.
+    */
+    fn lock(&mut self) -> MainResult<()> {
+        // The cache lock is taken first, so that the package directory can't be removed once we hold the package lock.
+        self.locks.push(lock::cache_shared()?);
+        if let Some(project_dir) = self.fingerprint_path.parent() {
+            self.locks.push(lock::package(project_dir, &self.origin)?);
+        }
+        Ok(())
+    }
+
+    /**
+    Build the package and write the resulting SPIR-V to the output path, or only check or lint it.
+
+    Returns the files the build depended on.
+    */
+    fn execute_command(&self) -> MainResult<Vec<PathBuf>> {
+        if self.check {
+            self.check()?;
+            return self.known_dependencies();
+        }
+        let build = self.build()?;
+        self.validate(&build.compile_result)?;
+        let outputs = self.write_output(&build.compile_result)?;
+        // Modules written per entry point are listed, as their paths aren't given on the command line.
+        if self.multimodule || outputs.len() > 1 {
+            for path in outputs {
+                eprintln!("{}", path.display());
+            }
+        }
+        Ok(build.dependencies)
+    }
+
+    /**
+    Build the package.
+
+    If the fingerprint of the previous build matches, the result of that build is returned without invoking cargo at all.
+    */
+    fn build(&self) -> MainResult<Build> {
+        self.build_from(fingerprint::load(&self.fingerprint_path))
+    }
+
+    /**
+    Build the package, given the record of the previous build instead of loading it.
+    */
+    fn build_from(&self, previous_build: Option<fingerprint::BuildRecord>) -> MainResult<Build> {
+        if let Some(cache_dir) = self.fingerprint_path.parent() {
+            cache::record_use(cache_dir, &self.origin)?;
+        }
+
+        let dependencies = self.fingerprint_dependencies(previous_build.as_ref())?;
+        let settings = self.fingerprint_settings();
+        let fingerprint = fingerprint::compute(&settings, &dependencies);
+
+        // The backend only writes dumps when it runs.
+        let previous_build = previous_build.filter(|_| self.dump_dir.is_none());
+        if let Some(previous_build) = previous_build {
+            if previous_build.fingerprint == fingerprint
+                && fingerprint::artifacts_exist(&previous_build.compile_result)
+            {
+                info!("fingerprint matches, skipping build");
+                return Ok(Build {
+                    fingerprint,
+                    compile_result: previous_build.compile_result,
+                    dependencies: previous_build.dependencies,
+                    diagnostics: Vec::new(),
+                });
+            }
+        }
+
+        let (artifact, compile_result, diagnostics) = self.run_cargo()?;
+
+        let mut build_dependencies = match build::leaf_deps(&artifact) {
+            Ok(deps) => deps,
+            Err(err) => {
+                debug!("could not read dep-info for {:?}: {}", artifact, err);
+                Vec::new()
+            }
+        };
+        // Files in the target directory are only changed by builds.
+        let binary_cache_path = platform::binary_cache_path()?;
+        build_dependencies.retain(|path| !path.starts_with(&binary_cache_path));
+        build_dependencies.extend(self.known_dependencies()?);
+        build_dependencies.sort();
+        build_dependencies.dedup();
+
+        // Files which were not hashed before the build need to be hashed now.
+        let fingerprint = if build_dependencies == dependencies {
+            fingerprint
+        } else {
+            fingerprint::compute(&settings, &build_dependencies)
+        };
+        let record = fingerprint::BuildRecord {
+            fingerprint,
+            dependencies: build_dependencies,
+            compile_result,
+        };
+        fingerprint::store(&self.fingerprint_path, &record)?;
+
+        Ok(Build {
+            fingerprint: record.fingerprint,
+            compile_result: record.compile_result,
+            dependencies: record.dependencies,
+            diagnostics,
+        })
+    }
+
+    /**
+    The files to fingerprint before building: those known before building and those the previous build depended on.
+    */
+    fn fingerprint_dependencies(
+        &self,
+        previous_build: Option<&fingerprint::BuildRecord>,
+    ) -> MainResult<Vec<PathBuf>> {
+        let mut dependencies = self.known_dependencies()?;
+        if let Some(previous_build) = previous_build {
+            dependencies.extend(previous_build.dependencies.iter().cloned());
+        }
+        dependencies.sort();
+        dependencies.dedup();
+        Ok(dependencies)
+    }
+
+    /**
+    Describe the package and whether its last build is up to date, as shown by `inspect`.
+    */
+    fn describe(&self) -> MainResult<String> {
+        let mut description = format!(
+            "package:    {}\nmanifest:   {}\norigin:     {}\n",
+            self.pkg_path.display(),
+            self.manifest_path().display(),
+            self.origin
+        );
+        match fingerprint::load(&self.fingerprint_path) {
+            Some(record) => {
+                let dependencies = self.fingerprint_dependencies(Some(&record))?;
+                let fingerprint = fingerprint::compute(&self.fingerprint_settings(), &dependencies);
+                let up_to_date = record.fingerprint == fingerprint
+                    && fingerprint::artifacts_exist(&record.compile_result);
+                description += &format!(
+                    "last build: {}\n",
+                    if up_to_date {
+                        "up to date"
+                    } else {
+                        "out of date"
+                    }
+                );
+                for path in module_paths(&record.compile_result) {
+                    description += &format!("module:     {}\n", path.display());
+                }
+            }
+            None => description += "last build: none\n",
+        }
+        Ok(description)
+    }
+
+    /**
+    The files the build is known to depend on before building: the script itself and the files of local `path` dependencies.
+    */
+    fn known_dependencies(&self) -> MainResult<Vec<PathBuf>> {
+        let mut dependencies = manifest::local_path_dependency_files(&self.manifest)?;
+        dependencies.extend(self.script_path.iter().cloned());
+        Ok(dependencies)
+    }
+
+    /**
+    Everything other than the contents of dependency files which affects the build output.
+    */
+    fn fingerprint_settings(&self) -> String {
+        let backend_path = platform::codegen_backend_path();
+        let backend = fs::metadata(&backend_path)
+            .map(|md| format!("{}:{:?}", md.len(), md.modified().ok()))
+            .unwrap_or_default();
+        format!(
+            "version={}\nbackend={}:{}\ntarget={}\ndebug={}\nmultimodule={}\ncodegen={:?}\nenv={:?}\nsource={:?}\nmanifest={}\n",
+            env!("CARGO_PKG_VERSION"),
+            backend_path.display(),
+            backend,
+            self.target,
+            self.debug,
+            self.multimodule,
+            self.codegen,
+            self.env,
+            self.generated_source,
+            self.manifest,
+        )
+    }
+
+    /**
+    Make cargo consider the package dirty, so that it runs the backend even if nothing changed.
+
+    This removes the package's entries in the `.fingerprint` directory of the target directory, named `<package>-<hash>`.
+    */
+    fn invalidate_cargo_fingerprint(&self) -> MainResult<()> {
+        let profile = if self.debug { "debug" } else { "release" };
+        let fingerprints_dir = platform::binary_cache_path()?
+            .join(&self.target)
+            .join(profile)
+            .join(".fingerprint");
+        let entries = match fs::read_dir(&fingerprints_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let is_package = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| is_cargo_fingerprint_of(name, &self.pkg_name));
+            if is_package {
+                info!("removing cargo fingerprint {:?}", path);
+                fs::remove_dir_all(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+    A cargo command of the bundled toolchain, to be run in the package directory with the environment of builds.
+    */
+    fn cargo_command(&self, subcommand: &str) -> Command {
+        let toolchain_path = platform::toolchain_path();
+        let mut cmd = Command::new(toolchain_path.join("bin").join("cargo"));
+        cmd.arg(subcommand);
+        cmd.env_clear();
+        // Otherwise 'cc' is not found when building proc macros for host, and subcommands of the toolchain such as
+        // clippy are not found by cargo:
+        let path = std::env::join_paths([toolchain_path.join("bin"), PathBuf::from("/usr/bin")])
+            .unwrap_or_else(|_| "/usr/bin".into());
+        cmd.env("PATH", path);
+        cmd.env("RUSTC", toolchain_path.join("bin").join("rustc"));
+        cmd.envs(self.env.iter().map(|(k, v)| (k, v)));
+        cmd.current_dir(&self.pkg_path);
+        cmd
+    }
+
+    /**
+    Run `cargo metadata` on the package, returning its output.
+    */
+    fn cargo_metadata(&self) -> MainResult<String> {
+        let output = self
+            .cargo_command("metadata")
+            .arg("--format-version=1")
+            .stderr(std::process::Stdio::inherit())
+            .output()
+            .map_err(|e| {
+                MainError::Tag(
+                    "failed to execute cargo metadata".into(),
+                    Box::new(e.into()),
+                )
+            })?;
+        if !output.status.success() {
+            return Err("cargo metadata failed".into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /**
+    Build the given proc macro packages, such as `spirv-std-macros@0.9.0`, with the bundled toolchain.
+
+    Returns the library of each, by package id.
+    */
+    fn build_proc_macros(&self, packages: &[String]) -> MainResult<HashMap<String, PathBuf>> {
+        if packages.is_empty() {
+            return Ok(HashMap::new());
+        }
+        // Proc macros are compiled for the host, so only the macros are built and not the shader.
+        let mut cmd = self.cargo_target_command("build")?;
+        for package in packages {
+            cmd.arg("-p").arg(package);
+        }
+        let (stdout, _) = self.spawn_cargo(cmd)?;
+        Ok(build::proc_macro_artifacts(&stdout))
+    }
+
+    /**
+    Run cargo to build the package.
+
+    Returns the path to the build metadata, its contents, and the compiler diagnostics if they are captured.
+    */
+    fn run_cargo(&self) -> MainResult<(PathBuf, CompileResult, Vec<String>)> {
+        let cmd = self.cargo_target_command("build")?;
+
+        if let Some(dump_dir) = &self.dump_dir {
+            codegen::prepare_dump_dir(dump_dir, &cmd)?;
+            self.invalidate_cargo_fingerprint()?;
+        }
+
+        let (stdout, diagnostics) = self.spawn_cargo(cmd)?;
+        let metadata = build::get_sole_artifact(&stdout).and_then(|artifact| {
+            let compile_result = build::parse_metadata_file(&artifact)?;
+            Ok((artifact, compile_result, diagnostics))
+        });
+        match metadata {
+            Ok(metadata) => Ok(metadata),
+            Err(error) => {
+                eprintln!("--- build output ---\n{stdout}");
+                eprintln!("--- error ---\n{error:?}");
+                Err("could not read build metadata".into())
+            }
+        }
+    }
+
+    /**
+    Check the package for errors with `cargo check`, or lint it with `cargo clippy`, without generating SPIR-V.
+
+    Returns the compiler diagnostics if they are captured.
+    */
+    fn check(&self) -> MainResult<Vec<String>> {
+        let cmd = match &self.lints {
+            Some(lints) => {
+                let clippy_path = platform::toolchain_path()
+                    .join("bin")
+                    .join(format!("cargo-clippy{}", std::env::consts::EXE_SUFFIX));
+                if !clippy_path.is_file() {
+                    return Err(format!(
+                        "clippy is not included in the rust-gpu toolchain, expected {}",
+                        clippy_path.display()
+                    )
+                    .into());
+                }
+                let mut cmd = self.cargo_target_command("clippy")?;
+                cmd.arg("--").args(lints);
+                cmd
+            }
+            None => self.cargo_target_command("check")?,
+        };
+        let (_, diagnostics) = self.spawn_cargo(cmd)?;
+        Ok(diagnostics)
+    }
+
+    /**
+    A cargo command compiling the package for the SPIR-V target with the codegen backend, such as `build` or `check`.
+    */
+    fn cargo_target_command(&self, subcommand: &str) -> MainResult<Command> {
+        let librustc_codegen_spirv_path = platform::codegen_backend_path();
+        let librustc_codegen_spirv_path = librustc_codegen_spirv_path.display();
+        let mut cmd = self.cargo_command(subcommand);
+
+        if self.capture_diagnostics {
+            cmd.arg("--message-format=json");
+        } else {
+            cmd.arg("--message-format=json-render-diagnostics");
+        }
+
+        // rust-gpu flags: https://embarkstudios.github.io/rust-gpu/book/writing-shader-crates.html
+        // TODO: Default, but take optional from cmdline arg
+        cmd.arg("--target");
+        cmd.arg(&self.target);
+        cmd.arg("-Zbuild-std=core");
+        cmd.arg("-Zbuild-std-features=compiler-builtins-mem");
+        let mut rustflags = vec![
+            format!("-Zcodegen-backend={librustc_codegen_spirv_path}"),
+            "-Zbinary-dep-depinfo".to_owned(),
+            "-Csymbol-mangling-version=v0".to_owned(),
+            "-Zcrate-attr=feature(register_tool)".to_owned(),
+            "-Zcrate-attr=register_tool(rust_gpu)".to_owned(),
+            "-Coverflow-checks=off".to_owned(),
+            "-Cdebug-assertions=off".to_owned(),
+            "-Zinline-mir=off".to_owned(),
+        ];
+
+        let mut llvm_args = Vec::new();
+        if self.multimodule {
+            llvm_args.push("--module-output=multiple".to_owned());
+        }
+        llvm_args.extend(self.codegen.llvm_args());
+        if !llvm_args.is_empty() {
+            rustflags.push(format!("-Cllvm-args={}", llvm_args.join(" ")));
+        }
+        rustflags.extend(self.codegen.target_feature_flag());
+
+        // Encoded as in spirv-builder, so that the flags may contain spaces.
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", rustflags.join("\x1f"));
+
+        if !self.cargo_output {
+            cmd.arg("-q");
+        }
+
+        if platform::force_cargo_color() && !self.capture_diagnostics {
+            cmd.arg("--color").arg("always");
+        }
+
+        let cargo_target_dir = format!("{}", platform::binary_cache_path()?.display(),);
+        cmd.arg("--target-dir");
+        cmd.arg(cargo_target_dir);
+
+        if !self.debug {
+            cmd.arg("--release");
+        }
+        Ok(cmd)
+    }
+
+    /**
+    Run a cargo command, passing on the diagnostics as they are emitted if there's a sender for them.
+
+    Returns the output of cargo, and the compiler diagnostics if they are captured.
+    */
+    fn spawn_cargo(&self, mut cmd: Command) -> MainResult<(String, Vec<String>)> {
+        let stderr = if self.capture_diagnostics {
+            std::process::Stdio::piped()
+        } else {
+            std::process::Stdio::inherit()
+        };
+        let mut child = cmd
+            .stdout(std::process::Stdio::piped())
+            .stderr(stderr)
+            // .current_dir(&builder.path_to_crate)
+            .spawn()
+            .map_err(|e| MainError::Tag("failed to execute cargo".into(), Box::new(e.into())))?;
+
+        // Stderr is read on its own thread, so that cargo doesn't block on one pipe while the other is read.
+        let stderr_reader = child.stderr.take().map(|mut stderr| {
+            std::thread::spawn(move || {
+                let mut contents = Vec::new();
+                let _ = stderr.read_to_end(&mut contents);
+                String::from_utf8_lossy(&contents).into_owned()
+            })
+        });
+
+        // Read the output as it comes, to pass on the diagnostics while the build is still running.
+        let mut stdout = String::new();
+        let mut reader = BufReader::new(child.stdout.take().expect("stdout should be piped"));
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            let text = String::from_utf8_lossy(&line);
+            if let Some(sender) = &self.diagnostics_sender {
+                if let Some(diagnostic) = build::diagnostic(&text) {
+                    // A receiver which went away doesn't affect the build.
+                    let _ = sender.send(diagnostic);
+                }
+            }
+            stdout.push_str(&text);
+            line.clear();
+        }
+        let status = child.wait()?;
+        let stderr = stderr_reader
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default();
+
+        let mut diagnostics = build::diagnostics(&stdout);
+        if status.code() != Some(0) {
+            // Panics of the backend and cargo's own errors are only on stderr.
+            if !stderr.trim().is_empty() {
+                diagnostics.push(stderr);
+            }
+            return Err(MainError::Build(diagnostics));
+        }
+        Ok((stdout, diagnostics))
+    }
+
+    /**
+    Validate the built modules for the target if requested, failing with the violations found.
+    */
+    fn validate(&self, compile_result: &CompileResult) -> MainResult<()> {
+        if !self.validate {
+            return Ok(());
+        }
+        let mut violations = Vec::new();
+        for path in module_paths(compile_result) {
+            violations.extend(validate::validate(&fs::read(path)?, &self.target)?);
+        }
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        Err(format!(
+            "module is not valid for {}:\n{}",
+            self.target,
+            violations.join("\n")
+        )
+        .into())
+    }
+
+    /**
+    Write the built SPIR-V to the output path.
+
+    Returns the paths of the modules written, which is none when writing to standard output.
+    */
+    fn write_output(&self, compile_result: &CompileResult) -> MainResult<Vec<PathBuf>> {
+        let outputs = match &compile_result.module {
+            ModuleResult::SingleModule(built_spirv_path) => self.write_module(
+                built_spirv_path,
+                Path::new(&self.spirv_output_path),
+                &compile_result.entry_points,
+            )?,
+            ModuleResult::MultiModule(modules) => {
+                let mut outputs = Vec::new();
+                for (entry, built_spirv_path) in modules {
+                    let output_path = self.module_output_path(entry);
+                    outputs.extend(self.write_module(
+                        built_spirv_path,
+                        &output_path,
+                        std::slice::from_ref(entry),
+                    )?);
+                }
+                outputs
+            }
+        };
+
+        if let Some(reflect_path) = &self.reflect_path {
+            let modules = module_paths(compile_result)
+                .into_iter()
+                .map(fs::read)
+                .collect::<Result<Vec<_>, _>>()?;
+            let reflection = reflect::reflect(&modules)?;
+            let json = serde_json::to_string_pretty(&reflection).map_err(|e| e.to_string())? + "\n";
+            if reflect_path == "-" {
+                std::io::stdout().write_all(json.as_bytes())?;
+            } else {
+                write_file(Path::new(reflect_path), json.as_bytes())?;
+            }
+        }
+        Ok(outputs)
+    }
+
+    /**
+    Write a built module to the given path, or to standard output for `-`, in the form given by `--emit`.
+
+    Translations to GLSL with several entry points are written to a file per entry point, as in multimodule mode.
+
+    Returns the paths of the files written.
+    */
+    fn write_module(
+        &self,
+        built_spirv_path: &Path,
+        output_path: &Path,
+        entry_points: &[String],
+    ) -> MainResult<Vec<PathBuf>> {
+        let spirv = fs::read(built_spirv_path)?;
+        let contents = match self.emit.as_str() {
+            "spvasm" => disasm::disassemble(&spirv)?.into_bytes(),
+            "rust" => {
+                embed::rust_source(&spirv, entry_points, &origin_name(&self.origin))?.into_bytes()
+            }
+            language if translate::LANGUAGES.contains(&language) => {
+                let mut translations = translate::translate(&spirv, language)?;
+                if translations.len() > 1 {
+                    if output_path == Path::new("-") {
+                        return Err(format!(
+                            "cannot write {} for multiple entry points to standard output",
+                            language.to_uppercase()
+                        )
+                        .into());
+                    }
+                    let mut outputs = Vec::new();
+                    for translation in translations {
+                        let entry = translation.entry_point.unwrap_or_default();
+                        let entry_output_path = self.module_output_path(&entry);
+                        write_file(&entry_output_path, translation.source.as_bytes())?;
+                        outputs.push(entry_output_path);
+                    }
+                    return Ok(outputs);
+                }
+                translations.remove(0).source.into_bytes()
+            }
+            _ => spirv,
+        };
+        if output_path == Path::new("-") {
+            std::io::stdout().write_all(&contents)?;
+            return Ok(Vec::new());
+        }
+        write_file(output_path, &contents)?;
+        Ok(vec![output_path.to_path_buf()])
+    }
+
+    /**
+    Where the module for the given entry point should be written in multimodule mode.
+
+    If the output path is an existing directory, or ends with a path separator, the module is written as `<entry>.spv` inside it. Otherwise it's written next to the output path as `<stem>.<entry>.spv`. The extension depends on `--emit`.
+    */
+    fn module_output_path(&self, entry: &str) -> PathBuf {
+        let file_safe_entry: String = entry
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+                _ => '_',
+            })
+            .collect();
+
+        let output_path = Path::new(&self.spirv_output_path);
+        if output_path.is_dir() || self.spirv_output_path.ends_with(std::path::is_separator) {
+            let extension = arguments::emit_extension(&self.emit);
+            return output_path.join(format!("{file_safe_entry}.{extension}"));
+        }
+
+        let stem = output_path
+            .file_stem()
+            .map(|os| os.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "shader".into());
+        let extension = arguments::emit_extension(&self.emit);
+        output_path.with_file_name(format!("{stem}.{file_safe_entry}.{extension}"))
+    }
+}
+
+/**
+The outcome of building a package.
+*/
+struct Build {
+    /// Fingerprint of the settings and dependencies of the build.
+    fingerprint: String,
+
+    compile_result: CompileResult,
+
+    /// The files the build depended on.
+    dependencies: Vec<PathBuf>,
+
+    /// The rendered compiler diagnostics, if they were captured.
+    diagnostics: Vec<String>,
+}
+
+/// If an entry of cargo's `.fingerprint` directory, named `<package>-<16 hex digits>`, belongs to the given package.
+fn is_cargo_fingerprint_of(entry_name: &str, pkg_name: &str) -> bool {
+    entry_name
+        .strip_prefix(pkg_name)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|hash| hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/**
+The origin of an input without the directories leading to it, such as `shader.rs` or `my-crate/Cargo.toml`.
+
+This is what generated source names, so that it doesn't depend on where the checkout is.
+*/
+fn origin_name(origin: &str) -> String {
+    let path = Path::new(origin);
+    let keep = if path.file_name() == Some("Cargo.toml".as_ref()) {
+        2
+    } else {
+        1
+    };
+    let components: Vec<_> = path.components().collect();
+    components[components.len().saturating_sub(keep)..]
+        .iter()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Paths of the modules in a compile result.
+fn module_paths(compile_result: &CompileResult) -> Vec<&Path> {
+    match &compile_result.module {
+        ModuleResult::SingleModule(path) => vec![path.as_path()],
+        ModuleResult::MultiModule(modules) => modules.values().map(PathBuf::as_path).collect(),
+    }
+}
+
+/**
+For the given input, this constructs the package metadata and checks the cache to see what should be done.
+*/
+fn decide_action_for(input: &Input, args: &Args) -> MainResult<InputAction> {
+    let input_id = input.compute_id();
+    info!("id: {:?}", input_id);
+
+    let pkg_name = input.package_name();
+    let bin_name = format!("{}_{}", &*pkg_name, input_id.to_string_lossy());
+
+    let existing_crate = matches!(input, Input::Crate(..));
+    if existing_crate && args.pkg_path.is_some() {
+        return Err("--pkg-path cannot be used when building an existing crate".into());
+    }
+
+    let (pkg_path, using_cache) = match &args.pkg_path {
+        Some(p) => (p.into(), false),
+        // The crate is built in place.
+        None if existing_crate => (input.base_path()?, false),
+        None => (
+            platform::generated_projects_cache_path()?.join(&input_id),
+            true,
+        ),
+    };
+    info!("pkg_path: {:?}", pkg_path);
+    info!("using_cache: {:?}", using_cache);
+
+    let fingerprint_path = platform::generated_projects_cache_path()?
+        .join(&input_id)
+        .join("fingerprint.json");
+
+    let base_path = match &args.base_path {
+        Some(path) => Path::new(path).into(),
+        None => input.base_path()?,
+    };
+
+    let (mani_str, generated_source) = match input {
+        Input::Crate(_, _, manifest) => (manifest.clone(), None),
+        _ => {
+            let (mani_str, script_path) = manifest::split_input(input, &base_path, &bin_name)?;
+            let generated_source = input
+                .generated_source()
+                .map(|content| (script_path, content.to_owned()));
+            (mani_str, generated_source)
+        }
+    };
+
+    let codegen = codegen::CodegenOptions::new(args, manifest::rust_gpu_metadata(&mani_str)?)?;
+
+    let extension = arguments::emit_extension(&args.emit);
+    let spirv_output_path = match &args.output_path {
+        // With multiple scripts the output path is a directory.
+        Some(output_path) if args.multiple_scripts() => {
+            let path = Path::new(output_path).join(format!("{}.{}", input.safe_name(), extension));
+            path.to_string_lossy().into_owned()
+        }
+        Some(output_path) => output_path.to_owned(),
+        None => match input.path() {
+            Some(path) => path
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned(),
+            // Crates default to writing next to their manifest.
+            None if existing_crate => {
+                let path = input
+                    .base_path()?
+                    .join(format!("{}.{}", input.safe_name(), extension));
+                path.to_string_lossy().into_owned()
+            }
+            // Inputs without a path default to writing to standard output.
+            None => "-".to_owned(),
+        },
+    };
+    if args.multimodule && spirv_output_path == "-" {
+        return Err("cannot write multiple modules to standard output".into());
+    }
+    if input.path() == Some(Path::new(&spirv_output_path)) {
+        return Err("the output would overwrite the script".into());
+    }
+
+    let reflect_path = match &args.reflect {
+        // With multiple scripts the reflection path is a directory.
+        Some(reflect_path) if args.multiple_scripts() => {
+            let path = Path::new(reflect_path).join(format!("{}.json", input.safe_name()));
+            Some(path.to_string_lossy().into_owned())
+        }
+        Some(reflect_path) => Some(reflect_path.to_owned()),
+        None => None,
+    };
+    if reflect_path.as_deref() == Some("-") && spirv_output_path == "-" {
+        return Err("cannot write both the output and the reflection to standard output".into());
+    }
+
+    // Setup environment variables so they're available at compilation time of scripts,
+    // to allow e.g. include!(concat!(env!("RUST_GPU_BASE_PATH"), "/script-module.rs"));
+    let mut env = vec![
+        (
+            "RUST_GPU_PATH",
+            input.path().unwrap_or_else(|| Path::new("")).into(),
+        ),
+        ("RUST_GPU_SAFE_NAME", input.safe_name().into()),
+        ("RUST_GPU_PKG_NAME", input.package_name().into()),
+        ("RUST_GPU_BASE_PATH", input.base_path()?.into()),
+    ];
+
+    let dump_dir = match &args.dump_dir {
+        // With multiple scripts each gets its own subdirectory.
+        Some(dump_dir) if args.multiple_scripts() => Some(
+            std::env::current_dir()?
+                .join(dump_dir)
+                .join(input.safe_name()),
+        ),
+        Some(dump_dir) => Some(std::env::current_dir()?.join(dump_dir)),
+        None => None,
+    };
+
+    // The backend reads options from RUSTGPU_CODEGEN_ARGS in addition to -Cllvm-args.
+    // As cargo doesn't track it, dumping doesn't cause a rebuild of all dependencies.
+    let mut codegen_args = std::env::var("RUSTGPU_CODEGEN_ARGS").unwrap_or_default();
+    if let Some(dump_dir) = &dump_dir {
+        if dump_dir.to_string_lossy().contains(char::is_whitespace) {
+            return Err(format!(
+                "the --dump-dir path cannot contain whitespace: {}",
+                dump_dir.display()
+            )
+            .into());
+        }
+        for arg in codegen::dump_args(dump_dir) {
+            codegen_args.push(' ');
+            codegen_args.push_str(&arg);
+        }
+    }
+    if !codegen_args.trim().is_empty() {
+        env.push(("RUSTGPU_CODEGEN_ARGS", codegen_args.trim().into()));
+    }
+
+    Ok(InputAction {
+        capture_diagnostics: false,
+        diagnostics_sender: None,
+        cargo_output: args.cargo_output,
+        check: args.check,
+        codegen,
+        debug: args.debug,
+        dump_dir,
+        emit: args.emit.clone(),
+        env,
+        existing_crate,
+        fingerprint_path,
+        generated_source,
+        lints: args.lints.clone(),
+        locks: Vec::new(),
+        manifest: mani_str,
+        multimodule: args.multimodule,
+        origin: input.origin(),
+        pkg_name: match input {
+            Input::Crate(name, ..) => name.clone(),
+            _ => bin_name,
+        },
+        pkg_path,
+        reflect_path,
+        script_path: input.path().map(Path::to_path_buf),
+        spirv_output_path,
+        target: args.target.clone(),
+        using_cache,
+        validate: args.validate,
+    })
+}
+
+/// Attempts to locate the script specified by the given path.
+fn find_script(path: &Path) -> Option<(PathBuf, fs::File)> {
+    if let Ok(file) = fs::File::open(path) {
+        return Some((path.into(), file));
+    }
+
+    if path.extension().is_none() {
+        for &ext in &["ers", "rs"] {
+            let path = path.with_extension(ext);
+            if let Ok(file) = fs::File::open(&path) {
+                return Some((path, file));
+            }
+        }
+    }
+
+    None
+}
+
+/**
+Represents an input source for a script.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Input {
+    /**
+    The input is a script file.
+
+    The tuple members are: the name, absolute path, script contents.
+    */
+    File(String, PathBuf, String),
+    /**
+    The input is a script read from standard input.
+
+    The member is the script contents.
+    */
+    Stdin(String),
+    /**
+    The input is an expression given on the command line.
+
+    The member is the script generated from the expression.
+    */
+    Expr(String),
+    /**
+    The input is an existing crate, which is built as-is.
+
+    The tuple members are: the package name, absolute path to the manifest, manifest contents.
+    */
+    Crate(String, PathBuf, String),
+}
+
+impl Input {
+    /**
+    Return the path to the script, if it has one.
+    */
+    pub fn path(&self) -> Option<&Path> {
+        use crate::Input::*;
+
+        match self {
+            File(_, path, _) => Some(path),
+            Stdin(_) | Expr(_) | Crate(..) => None,
+        }
+    }
+
+    /**
+    Describe where the input came from.
+    */
+    pub fn origin(&self) -> String {
+        use crate::Input::*;
+
+        match self {
+            File(_, path, _) | Crate(_, path, _) => path.display().to_string(),
+            Stdin(_) => "<stdin>".to_string(),
+            Expr(_) => "<expr>".to_string(),
+        }
+    }
+
+    /**
+    Return the "safe name" for the input.  This should be filename-safe.
+
+    Currently, nothing is done to ensure this, other than hoping *really hard* that we don't get fed some excessively bizarre input filename.
+    */
+    pub fn safe_name(&self) -> &str {
+        use crate::Input::*;
+
+        match self {
+            File(name, _, _) => name,
+            Stdin(_) => "stdin",
+            Expr(_) => "expr",
+            Crate(name, _, _) => name,
+        }
+    }
+
+    /**
+    Return the package name for the input.  This should be a valid Rust identifier.
+    */
+    pub fn package_name(&self) -> String {
+        let name = self.safe_name();
+        let mut r = String::with_capacity(name.len());
+
+        for (i, c) in name.chars().enumerate() {
+            match (i, c) {
+                (0, '0'..='9') => {
+                    r.push('_');
+                    r.push(c);
+                }
+                (_, '0'..='9') | (_, 'a'..='z') | (_, '_') | (_, '-') => {
+                    r.push(c);
+                }
+                (_, 'A'..='Z') => {
+                    // Convert uppercase characters to lowercase to avoid `non_snake_case` warnings.
+                    r.push(c.to_ascii_lowercase());
+                }
+                (_, _) => {
+                    r.push('_');
+                }
+            }
+        }
+
+        r
+    }
+
+    /**
+    Return the script contents, if they have to be written into the generated package.
+
+    This is the case for all inputs which aren't script files.
+    */
+    pub fn generated_source(&self) -> Option<&str> {
+        use crate::Input::*;
+
+        match self {
+            File(..) | Crate(..) => None,
+            Stdin(content) | Expr(content) => Some(content),
+        }
+    }
+
+    /**
+    Base directory for resolving relative paths.
+    */
+    pub fn base_path(&self) -> MainResult<PathBuf> {
+        match self {
+            Self::File(_, path, _) => path.parent().map(Path::to_path_buf).ok_or_else(|| {
+                format!("couldn't get parent directory of {}", path.display()).into()
+            }),
+            Self::Stdin(_) | Self::Expr(_) => std::env::current_dir().map_err(|e| {
+                MainError::Tag(
+                    "couldn't get current directory for input base path".into(),
+                    Box::new(e.into()),
+                )
+            }),
+            Self::Crate(_, manifest_path, _) => manifest_path
+                .parent()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| {
+                    format!(
+                        "couldn't get parent directory of {}",
+                        manifest_path.display()
+                    )
+                    .into()
+                }),
+        }
+    }
+
+    // Compute the package ID for the input.
+    // This is used as the name of the cache folder into which the Cargo package
+    // will be generated.
+    pub fn compute_id(&self) -> OsString {
+        use crate::Input::*;
+
+        match self {
+            File(_, path, _) | Crate(_, path, _) => {
+                let mut hasher = Sha1::new();
+
+                // Hash the path to the script.
+                hasher.update(&*path.to_string_lossy());
+                let mut digest = format!("{:x}", hasher.finalize());
+                digest.truncate(consts::ID_DIGEST_LEN_MAX);
+
+                let mut id = OsString::new();
+                id.push(&*digest);
+                id
+            }
+            Stdin(content) | Expr(content) => {
+                let mut hasher = Sha1::new();
+
+                // Hash the kind of input and the script contents, as there is no path.
+                hasher.update(self.safe_name().as_bytes());
+                hasher.update(b":");
+                hasher.update(content.as_bytes());
+                let mut digest = format!("{:x}", hasher.finalize());
+                digest.truncate(consts::ID_DIGEST_LEN_MAX);
+
+                let mut id = OsString::new();
+                id.push(&*digest);
+                id
+            }
+        }
+    }
+}
+
+// Overwrite a file if and only if the contents have changed.
+/**
+Write a file, creating its parent directory if needed.
+*/
+fn write_file(path: &Path, contents: &[u8]) -> MainResult<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+fn overwrite_file(path: &Path, content: &str) -> MainResult<()> {
+    debug!("overwrite_file({:?}, _)", path);
+    let mut existing_content = String::new();
+    match fs::File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut existing_content)?;
+            if existing_content == content {
+                debug!("Equal content");
+                return Ok(());
+            }
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            // Continue
+        }
+        Err(e) => {
+            return Err(error::MainError::Io(e));
+        }
+    }
+
+    debug!(".. files differ");
+    let dir = path.parent().ok_or("The given path should be a file")?;
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    temp_file.write_all(content.as_bytes())?;
+    temp_file.flush()?;
+    temp_file.persist(path).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[test]
+fn test_stdin_id() {
+    let a = Input::Stdin("fn a() {}".to_string());
+    let b = Input::Stdin("fn b() {}".to_string());
+    assert_eq!(a.compute_id(), a.clone().compute_id());
+    assert_ne!(a.compute_id(), b.compute_id());
+    assert_eq!("stdin", a.package_name());
+}
+
+#[test]
+fn test_package_name() {
+    let input = Input::File(
+        "Script".to_string(),
+        Path::new("path").into(),
+        "script".to_string(),
+    );
+    assert_eq!("script", input.package_name());
+    let input = Input::File(
+        "1Script".to_string(),
+        Path::new("path").into(),
+        "script".to_string(),
+    );
+    assert_eq!("_1script", input.package_name());
+}
+
+#[test]
+fn test_is_cargo_fingerprint_of() {
+    let pkg_name = "shader_e1cf328a200193a061eeaf7f";
+    assert!(is_cargo_fingerprint_of(
+        "shader_e1cf328a200193a061eeaf7f-5f2a9c1d0b7e4a36",
+        pkg_name
+    ));
+    assert!(!is_cargo_fingerprint_of(
+        "shader-5f2a9c1d0b7e4a36",
+        pkg_name
+    ));
+    assert!(!is_cargo_fingerprint_of(
+        "shader_e1cf328a200193a061eeaf7f_x-5f2a9c1d0b7e4a36",
+        pkg_name
+    ));
+    assert!(!is_cargo_fingerprint_of(
+        "spirv-std-5f2a9c1d0b7e4a36",
+        pkg_name
+    ));
+}
+
+#[test]
+fn test_origin_name() {
+    assert_eq!(origin_name("/home/user/shaders/shader.rs"), "shader.rs");
+    assert_eq!(
+        origin_name("/home/user/shaders/my-crate/Cargo.toml"),
+        Path::new("my-crate").join("Cargo.toml").to_string_lossy()
+    );
+    assert_eq!(origin_name("<stdin>"), "<stdin>");
+}
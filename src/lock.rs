@@ -0,0 +1,109 @@
+/*!
+Advisory file locks, keeping concurrent invocations from stepping on each other's state in the cache.
+
+Builds hold the cache lock shared and the lock of their package exclusively, while cleaning the cache requires the cache lock exclusively.
+*/
+
+use fs4::FileExt;
+use log::info;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use crate::error::MainResult;
+use crate::platform;
+
+/// Name of the lock file of a package, in its directory in the cache.
+pub const PACKAGE_LOCK_FILE: &str = "lock";
+
+/**
+A held lock, which is released when dropped.
+*/
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Shared,
+    Exclusive,
+}
+
+// Open the lock file at the given path, creating it and its parent directory if needed.
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+fn is_contended(err: &io::Error) -> bool {
+    err.raw_os_error() == fs4::lock_contended_error().raw_os_error()
+}
+
+// Try to acquire the lock, returning whether it was acquired. The `FileExt` methods are called explicitly since newer
+// versions of std have inherent methods with the same names.
+fn try_lock(file: &File, mode: Mode) -> io::Result<bool> {
+    let result = match mode {
+        Mode::Shared => FileExt::try_lock_shared(file),
+        Mode::Exclusive => FileExt::try_lock_exclusive(file),
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(err) if is_contended(&err) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+// Acquire the lock, telling the user if we have to wait for it.
+fn lock(path: &Path, mode: Mode, what: &str) -> MainResult<FileLock> {
+    info!("locking {:?}", path);
+    let file = open(path)?;
+    if !try_lock(&file, mode)? {
+        eprintln!("waiting for lock on {}...", what);
+        match mode {
+            Mode::Shared => FileExt::lock_shared(&file)?,
+            Mode::Exclusive => FileExt::lock_exclusive(&file)?,
+        }
+    }
+    Ok(FileLock { _file: file })
+}
+
+/**
+Lock the cache for building, which may be done by several processes at once.
+*/
+pub fn cache_shared() -> MainResult<FileLock> {
+    lock(&platform::cache_lock_path()?, Mode::Shared, "the cache")
+}
+
+/**
+Lock the cache for cleaning, waiting for all builds using it to finish.
+*/
+pub fn cache_exclusive() -> MainResult<FileLock> {
+    lock(&platform::cache_lock_path()?, Mode::Exclusive, "the cache")
+}
+
+/**
+Lock the cache for cleaning if it isn't in use, returning `None` otherwise.
+*/
+pub fn try_cache_exclusive() -> MainResult<Option<FileLock>> {
+    let file = open(&platform::cache_lock_path()?)?;
+    Ok(try_lock(&file, Mode::Exclusive)?.then_some(FileLock { _file: file }))
+}
+
+/**
+Lock the package with the given directory in the cache, for the package built from `origin`.
+*/
+pub fn package(dir: &Path, origin: &str) -> MainResult<FileLock> {
+    lock(
+        &dir.join(PACKAGE_LOCK_FILE),
+        Mode::Exclusive,
+        &format!("the package for {}", origin),
+    )
+}
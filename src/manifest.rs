@@ -26,6 +26,15 @@ pub fn split_input(
 
             (manifest, path.clone())
         }
+        Input::Stdin(content) | Input::Expr(content) => {
+            let manifest = find_embedded_manifest(content).unwrap_or(Manifest::Toml(""));
+
+            // The source is written into the package next to the manifest.
+            (manifest, PathBuf::from(format!("{}.rs", input.safe_name())))
+        }
+        Input::Crate(..) => {
+            return Err("an existing crate has no embedded manifest to split".into());
+        }
     };
 
     let part_mani = part_mani.into_toml()?;
@@ -61,7 +70,7 @@ fn test_split_input() {
     let bin_name = "binary-name".to_string();
     macro_rules! si {
         ($i:expr) => {
-            split_input(&$i, &$i.base_path(), &bin_name).ok()
+            split_input(&$i, &$i.base_path().unwrap(), &bin_name).ok()
         };
     }
 
@@ -146,7 +155,7 @@ version = "0.1.0""#,
     assert_eq!(
         split_input(
             &f(r#"fn main() {}"#),
-            &f(r#"fn main() {}"#).base_path(),
+            &f(r#"fn main() {}"#).base_path().unwrap(),
             &bin_name,
         )
         .ok(),
@@ -971,6 +980,53 @@ time = "*"
     );
 }
 
+/**
+Reads the manifest of an existing shader crate, checking that it can be built by rust-gpu.
+
+Returns the package name and the manifest, with relative dependency paths made absolute.
+*/
+pub fn read_crate_manifest(manifest_path: &Path) -> MainResult<(String, String)> {
+    let content = std::fs::read_to_string(manifest_path).map_err(|e| {
+        MainError::Tag(
+            format!("cannot read crate manifest {}", manifest_path.display()).into(),
+            Box::new(e.into()),
+        )
+    })?;
+    let mani: toml::value::Table = toml::from_str(&content).map_err(|e| {
+        MainError::Tag(
+            "could not parse crate manifest".into(),
+            Box::new(MainError::Other(Box::new(e))),
+        )
+    })?;
+
+    let name = mani
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str)
+        .ok_or_else(|| format!("{} has no package name", manifest_path.display()))?
+        .to_owned();
+
+    let is_dylib = mani
+        .get("lib")
+        .and_then(|lib| lib.get("crate-type"))
+        .and_then(toml::Value::as_array)
+        .is_some_and(|crate_types| crate_types.iter().any(|t| t.as_str() == Some("dylib")));
+    if !is_dylib {
+        return Err(format!(
+            "{} must have `crate-type = [\"dylib\"]` in its [lib] section to be built as a shader",
+            manifest_path.display()
+        )
+        .into());
+    }
+
+    let base = manifest_path
+        .parent()
+        .ok_or("The given manifest path should be a file")?;
+    let mani = fix_manifest_paths(mani, base)?;
+
+    Ok((name, format!("{}", mani)))
+}
+
 /**
 Generates a default Cargo manifest for the given input.
 */
@@ -1122,6 +1178,63 @@ fn fix_manifest_paths(mani: toml::value::Table, base: &Path) -> MainResult<toml:
     }
 }
 
+/**
+Returns the files making up the local `path` dependencies of the given (already path-fixed) manifest.
+
+The `target` directories of the dependencies, and hidden files and directories, are skipped.
+*/
+pub fn local_path_dependency_files(mani_str: &str) -> MainResult<Vec<PathBuf>> {
+    let paths: &[&[&str]] = &[
+        &["build-dependencies", "*", "path"],
+        &["dependencies", "*", "path"],
+        &["target", "*", "dependencies", "*", "path"],
+    ];
+
+    let mut mani: toml::Value = toml::from_str(mani_str).map_err(|e| {
+        MainError::Tag(
+            "could not parse generated manifest".into(),
+            Box::new(MainError::Other(Box::new(e))),
+        )
+    })?;
+
+    let mut dependency_dirs = Vec::new();
+    for path in paths {
+        iterate_toml_mut_path(&mut mani, path, &mut |v| {
+            if let toml::Value::String(s) = v {
+                dependency_dirs.push(PathBuf::from(&*s));
+            }
+            Ok(())
+        })?
+    }
+
+    let mut files = Vec::new();
+    while let Some(dir) = dependency_dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!("could not read dependency directory {:?}: {}", dir, err);
+                continue;
+            }
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let hidden_or_target = path
+                .file_name()
+                .is_some_and(|name| name == "target" || name.to_string_lossy().starts_with('.'));
+            if hidden_or_target {
+                continue;
+            }
+            if path.is_dir() {
+                dependency_dirs.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
 /**
 Iterates over the specified TOML values via a path specification.
 */
@@ -1154,3 +1267,29 @@ where
 
     Ok(())
 }
+
+/**
+Returns the `[package.metadata.rust-gpu]` table of the given manifest, or the defaults if there is none.
+*/
+pub fn rust_gpu_metadata(mani_str: &str) -> MainResult<crate::codegen::Metadata> {
+    let mani: toml::Value = toml::from_str(mani_str).map_err(|e| {
+        MainError::Tag(
+            "could not parse generated manifest".into(),
+            Box::new(MainError::Other(Box::new(e))),
+        )
+    })?;
+
+    let metadata = mani
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("rust-gpu"));
+    match metadata {
+        Some(metadata) => metadata.clone().try_into().map_err(|e| {
+            MainError::Tag(
+                "invalid [package.metadata.rust-gpu] table".into(),
+                Box::new(MainError::Other(Box::new(e))),
+            )
+        }),
+        None => Ok(Default::default()),
+    }
+}
@@ -6,7 +6,10 @@ pub use self::inner::force_cargo_color;
 
 use std::fs;
 
-use std::path::PathBuf;
+use crate::error::MainResult;
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 // Last-modified time of a directory, in milliseconds since the UNIX epoch.
@@ -27,19 +30,30 @@ pub fn current_time() -> u128 {
         .as_millis()
 }
 
-pub fn cache_dir() -> PathBuf {
+/// Cache directory given on the command line, which takes precedence over everything else.
+static CACHE_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/**
+Use the given directory as cache directory, as given by `--cache-dir`.
+
+Must be called before the cache directory is first used.
+*/
+pub fn set_cache_dir(dir: PathBuf) {
+    if CACHE_DIR_OVERRIDE.set(dir).is_err() {
+        log::error!("cache directory already set");
+    }
+}
+
+pub fn cache_dir() -> MainResult<PathBuf> {
     #[cfg(not(test))]
     {
-        let mut path_buf = std::env::current_exe().unwrap();
-        path_buf.pop();
-        path_buf.pop();
-        path_buf = path_buf.join("var").join("cache").join("rust-gpu");
-        path_buf
-        /*
-        dirs::cache_dir()
-            .map(|dir| dir.join(crate::consts::PROGRAM_NAME))
-            .expect("Cannot get cache directory")
-        */
+        static CACHE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+        CACHE_DIR
+            .get_or_init(resolve_cache_dir)
+            .clone()
+            .ok_or_else(|| {
+                "cannot find a cache directory, use --cache-dir or set RUST_GPU_CACHE_DIR".into()
+            })
     }
     #[cfg(test)]
     {
@@ -47,16 +61,118 @@ pub fn cache_dir() -> PathBuf {
         lazy_static! {
             static ref TEMP_DIR: tempfile::TempDir = tempfile::TempDir::new().unwrap();
         }
-        TEMP_DIR.path().to_path_buf()
+        Ok(TEMP_DIR.path().to_path_buf())
+    }
+}
+
+/**
+Decide which directory to use as cache directory.
+
+In order of precedence, this is the directory given by `--cache-dir`, the `RUST_GPU_CACHE_DIR` environment variable, `var/cache/rust-gpu` in the install directory and the user cache directory. The install directory is skipped if it isn't writable, such as when installed in a read-only system prefix.
+
+Returns `None` if none of these is available.
+*/
+#[cfg(not(test))]
+fn resolve_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = CACHE_DIR_OVERRIDE.get() {
+        return Some(dir.clone());
+    }
+
+    if let Some(dir) = std::env::var_os("RUST_GPU_CACHE_DIR").filter(|dir| !dir.is_empty()) {
+        let dir = PathBuf::from(dir);
+        return match std::env::current_dir() {
+            Ok(current_dir) => Some(current_dir.join(dir)),
+            Err(_) => Some(dir),
+        };
     }
+
+    let install_cache_dir = install_dir().join("var").join("cache").join("rust-gpu");
+    if is_writable_dir(&install_cache_dir) {
+        return Some(install_cache_dir);
+    }
+    log::info!(
+        "{:?} is not writable, using the user cache directory",
+        install_cache_dir
+    );
+
+    dirs::cache_dir().map(|dir| dir.join(crate::consts::PROGRAM_NAME))
+}
+
+// Check if files can be created in the given directory, creating it if needed.
+#[cfg(not(test))]
+fn is_writable_dir(dir: &std::path::Path) -> bool {
+    fs::create_dir_all(dir).is_ok() && tempfile::tempfile_in(dir).is_ok()
+}
+
+/**
+The directory rust-gpu is installed in.
+
+This is the parent of the `bin` directory containing the executable, with the toolchain in `share/rust-gpu-toolchain` and the codegen backend in `lib`.
+*/
+pub fn install_dir() -> PathBuf {
+    static INSTALL_DIR: OnceLock<PathBuf> = OnceLock::new();
+    INSTALL_DIR.get_or_init(resolve_install_dir).clone()
+}
+
+/**
+Decide which directory rust-gpu is installed in.
+
+When the driver is embedded in another program through the library API, the current executable isn't `rust-gpu`. The `RUST_GPU_INSTALL_DIR` environment variable is used in that case, or else the installation of the `rust-gpu` binary on the `PATH`.
+*/
+fn resolve_install_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("RUST_GPU_INSTALL_DIR").filter(|dir| !dir.is_empty()) {
+        return dir.into();
+    }
+
+    let exe_install_dir = |exe: PathBuf| exe.parent()?.parent().map(Path::to_path_buf);
+    let has_toolchain = |dir: &PathBuf| dir.join("share").join("rust-gpu-toolchain").is_dir();
+
+    let current_exe_dir = std::env::current_exe().ok().and_then(exe_install_dir);
+    if let Some(dir) = current_exe_dir.as_ref().filter(|dir| has_toolchain(dir)) {
+        return dir.clone();
+    }
+
+    // The binary on the path may be a symlink into the installation, as with homebrew.
+    let binary_name = format!(
+        "{}{}",
+        crate::consts::PROGRAM_NAME,
+        std::env::consts::EXE_SUFFIX
+    );
+    let path_install_dir = std::env::var_os("PATH").and_then(|path| {
+        std::env::split_paths(&path)
+            .map(|dir| dir.join(&binary_name))
+            .filter_map(|binary| fs::canonicalize(binary).ok())
+            .filter_map(exe_install_dir)
+            .find(has_toolchain)
+    });
+    path_install_dir.or(current_exe_dir).unwrap_or_default()
+}
+
+pub fn toolchain_path() -> PathBuf {
+    install_dir().join("share").join("rust-gpu-toolchain")
+}
+
+pub fn codegen_backend_path() -> PathBuf {
+    let lib_suffix = if cfg!(target_vendor = "apple") {
+        "dylib"
+    } else {
+        "so"
+    };
+    install_dir()
+        .join("lib")
+        .join(format!("librustc_codegen_spirv.{lib_suffix}"))
+}
+
+pub fn generated_projects_cache_path() -> MainResult<PathBuf> {
+    Ok(cache_dir()?.join("projects"))
 }
 
-pub fn generated_projects_cache_path() -> PathBuf {
-    cache_dir().join("projects")
+pub fn binary_cache_path() -> MainResult<PathBuf> {
+    Ok(cache_dir()?.join("binaries"))
 }
 
-pub fn binary_cache_path() -> PathBuf {
-    cache_dir().join("binaries")
+pub fn cache_lock_path() -> MainResult<PathBuf> {
+    Ok(cache_dir()?.join("lock"))
 }
 
 #[cfg(unix)]
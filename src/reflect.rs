@@ -0,0 +1,689 @@
+/*!
+Reflection of built SPIR-V modules, describing their entry points and the resources they use as JSON for host code.
+*/
+
+use rspirv::dr::{Instruction, Module, Operand};
+use rspirv::spirv::{Decoration, Dim, Op, StorageClass, Word};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::error::MainResult;
+
+#[derive(Debug, Serialize)]
+pub struct Reflection {
+    pub entry_points: Vec<EntryPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntryPoint {
+    pub name: String,
+    pub execution_model: String,
+    pub execution_modes: Vec<ExecutionMode>,
+    /// The workgroup size of compute shaders, from the `LocalSize` execution mode.
+    pub local_size: Option<[u32; 3]>,
+    pub inputs: Vec<Variable>,
+    pub outputs: Vec<Variable>,
+    pub bindings: Vec<Binding>,
+    pub push_constants: Vec<PushConstantRange>,
+    pub specialization_constants: Vec<SpecializationConstant>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExecutionMode {
+    pub mode: String,
+    pub operands: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Variable {
+    pub name: Option<String>,
+    pub location: Option<u32>,
+    pub builtin: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Binding {
+    pub set: u32,
+    pub binding: u32,
+    pub name: Option<String>,
+    pub descriptor_type: String,
+    /// Number of descriptors, which is `None` for runtime arrays.
+    pub count: Option<u32>,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Size in bytes of buffers, not counting a trailing runtime array.
+    pub size: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PushConstantRange {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpecializationConstant {
+    pub id: u32,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub default: serde_json::Value,
+}
+
+/**
+Reflect the given SPIR-V modules, which are the separate modules of a multimodule build or a single module.
+*/
+pub fn reflect(modules: &[Vec<u8>]) -> MainResult<Reflection> {
+    let mut entry_points = Vec::new();
+    for bytes in modules {
+        let module = rspirv::dr::load_bytes(bytes)
+            .map_err(|e| format!("could not parse SPIR-V module: {}", e))?;
+        let info = ModuleInfo::new(&module);
+        for inst in &module.entry_points {
+            entry_points.push(info.entry_point(inst)?);
+        }
+    }
+    Ok(Reflection { entry_points })
+}
+
+/**
+Lookup tables for the names, decorations and definitions of the ids in a module.
+*/
+struct ModuleInfo<'a> {
+    module: &'a Module,
+    names: HashMap<Word, &'a str>,
+    decorations: HashMap<Word, Vec<&'a Instruction>>,
+    member_decorations: HashMap<(Word, u32), Vec<&'a Instruction>>,
+    definitions: HashMap<Word, &'a Instruction>,
+}
+
+impl<'a> ModuleInfo<'a> {
+    fn new(module: &'a Module) -> Self {
+        let mut info = Self {
+            module,
+            names: HashMap::new(),
+            decorations: HashMap::new(),
+            member_decorations: HashMap::new(),
+            definitions: HashMap::new(),
+        };
+
+        for inst in &module.debug_names {
+            if let (Op::Name, [Operand::IdRef(id), Operand::LiteralString(name)]) =
+                (inst.class.opcode, inst.operands.as_slice())
+            {
+                info.names.insert(*id, name);
+            }
+        }
+
+        for inst in &module.annotations {
+            match (inst.class.opcode, inst.operands.as_slice()) {
+                (Op::Decorate, [Operand::IdRef(id), ..]) => {
+                    info.decorations.entry(*id).or_default().push(inst);
+                }
+                (Op::MemberDecorate, [Operand::IdRef(id), Operand::LiteralInt32(member), ..]) => {
+                    info.member_decorations
+                        .entry((*id, *member))
+                        .or_default()
+                        .push(inst);
+                }
+                _ => {}
+            }
+        }
+
+        for inst in &module.types_global_values {
+            if let Some(id) = inst.result_id {
+                info.definitions.insert(id, inst);
+            }
+        }
+
+        info
+    }
+
+    fn name(&self, id: Word) -> Option<String> {
+        self.names.get(&id).map(|name| name.to_string())
+    }
+
+    // The operands following the decoration, if the id has it.
+    fn decoration(&self, id: Word, decoration: Decoration) -> Option<&'a [Operand]> {
+        self.decorations
+            .get(&id)?
+            .iter()
+            .find_map(|inst| match inst.operands.as_slice() {
+                [_, Operand::Decoration(d), rest @ ..] if *d == decoration => Some(rest),
+                _ => None,
+            })
+    }
+
+    fn decoration_u32(&self, id: Word, decoration: Decoration) -> Option<u32> {
+        match self.decoration(id, decoration)? {
+            [Operand::LiteralInt32(value), ..] => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn member_decoration_u32(&self, id: Word, member: u32, decoration: Decoration) -> Option<u32> {
+        self.member_decorations
+            .get(&(id, member))?
+            .iter()
+            .find_map(|inst| match inst.operands.as_slice() {
+                [_, _, Operand::Decoration(d), Operand::LiteralInt32(value), ..]
+                    if *d == decoration =>
+                {
+                    Some(*value)
+                }
+                _ => None,
+            })
+    }
+
+    fn builtin(&self, id: Word) -> Option<String> {
+        match self.decoration(id, Decoration::BuiltIn)? {
+            [Operand::BuiltIn(builtin), ..] => Some(format!("{:?}", builtin)),
+            _ => None,
+        }
+    }
+
+    fn id_operand(inst: &Instruction, idx: usize) -> Option<Word> {
+        match inst.operands.get(idx) {
+            Some(Operand::IdRef(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    fn literal_operand(inst: &Instruction, idx: usize) -> Option<u32> {
+        match inst.operands.get(idx) {
+            Some(Operand::LiteralInt32(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // The value of an integer constant.
+    fn constant_u32(&self, id: Word) -> Option<u32> {
+        let inst = self.definitions.get(&id)?;
+        match inst.class.opcode {
+            Op::Constant | Op::SpecConstant => Self::literal_operand(inst, 0),
+            _ => None,
+        }
+    }
+
+    // A readable name for a type, such as `vec4<f32>` or the name of a struct.
+    fn type_name(&self, ty: Word) -> String {
+        let Some(inst) = self.definitions.get(&ty) else {
+            return format!("%{}", ty);
+        };
+        let component = |idx| {
+            Self::id_operand(inst, idx)
+                .map(|id| self.type_name(id))
+                .unwrap_or_default()
+        };
+        match inst.class.opcode {
+            Op::TypeVoid => "void".into(),
+            Op::TypeBool => "bool".into(),
+            Op::TypeInt => {
+                let width = Self::literal_operand(inst, 0).unwrap_or(32);
+                let signed = Self::literal_operand(inst, 1) == Some(1);
+                format!("{}{}", if signed { "i" } else { "u" }, width)
+            }
+            Op::TypeFloat => format!("f{}", Self::literal_operand(inst, 0).unwrap_or(32)),
+            Op::TypeVector => format!(
+                "vec{}<{}>",
+                Self::literal_operand(inst, 1).unwrap_or(0),
+                component(0)
+            ),
+            Op::TypeMatrix => {
+                let columns = Self::literal_operand(inst, 1).unwrap_or(0);
+                let column = Self::id_operand(inst, 0).and_then(|id| self.definitions.get(&id));
+                let rows = column.and_then(|column| Self::literal_operand(column, 1));
+                let scalar = column
+                    .and_then(|column| Self::id_operand(column, 0))
+                    .map(|id| self.type_name(id))
+                    .unwrap_or_default();
+                format!("mat{}x{}<{}>", columns, rows.unwrap_or(0), scalar)
+            }
+            Op::TypeArray => {
+                let length = Self::id_operand(inst, 1).and_then(|id| self.constant_u32(id));
+                format!("array<{}, {}>", component(0), length.unwrap_or(0))
+            }
+            Op::TypeRuntimeArray => format!("array<{}>", component(0)),
+            Op::TypeStruct => self.name(ty).unwrap_or_else(|| "struct".into()),
+            Op::TypeImage => match inst.operands.get(1) {
+                Some(Operand::Dim(dim)) => format!("image{}", &format!("{:?}", dim)[3..]),
+                _ => "image".into(),
+            },
+            Op::TypeSampledImage => format!("sampled_{}", component(0)),
+            Op::TypeSampler => "sampler".into(),
+            Op::TypePointer => match inst.operands.first() {
+                Some(Operand::StorageClass(storage_class)) => {
+                    format!("ptr<{:?}, {}>", storage_class, component(1))
+                }
+                _ => "ptr".into(),
+            },
+            Op::TypeAccelerationStructureKHR => "acceleration_structure".into(),
+            _ => format!("%{}", ty),
+        }
+    }
+
+    /**
+    Size in bytes of a type with explicit layout, as in buffers and push constants.
+
+    Runtime arrays have size 0, so structs ending in one get the size of the part before it.
+    */
+    fn type_size(&self, ty: Word) -> Option<u32> {
+        let inst = self.definitions.get(&ty)?;
+        match inst.class.opcode {
+            Op::TypeBool => Some(4),
+            Op::TypeInt | Op::TypeFloat => Some(Self::literal_operand(inst, 0)? / 8),
+            Op::TypeVector | Op::TypeMatrix => {
+                Some(Self::literal_operand(inst, 1)? * self.type_size(Self::id_operand(inst, 0)?)?)
+            }
+            Op::TypeArray => {
+                let element = Self::id_operand(inst, 0)?;
+                let length = self.constant_u32(Self::id_operand(inst, 1)?)?;
+                let stride = match self.decoration_u32(ty, Decoration::ArrayStride) {
+                    Some(stride) => stride,
+                    None => self.type_size(element)?,
+                };
+                Some(length * stride)
+            }
+            Op::TypeRuntimeArray => Some(0),
+            Op::TypeStruct => {
+                let mut size = 0;
+                for (member, operand) in inst.operands.iter().enumerate() {
+                    let Operand::IdRef(member_type) = operand else {
+                        return None;
+                    };
+                    let member = member as u32;
+                    let offset = self.member_decoration_u32(ty, member, Decoration::Offset)?;
+                    // Matrices in structs are laid out with the stride given by the struct.
+                    let member_size = match (
+                        self.definitions
+                            .get(member_type)
+                            .map(|inst| inst.class.opcode),
+                        self.member_decoration_u32(ty, member, Decoration::MatrixStride),
+                    ) {
+                        (Some(Op::TypeMatrix), Some(stride)) => {
+                            let columns = Self::literal_operand(self.definitions[member_type], 1)?;
+                            columns * stride
+                        }
+                        _ => self.type_size(*member_type)?,
+                    };
+                    size = size.max(offset + member_size);
+                }
+                Some(size)
+            }
+            _ => None,
+        }
+    }
+
+    // The ids used by the entry point: those used in the functions it calls, and the types and constants they use in turn.
+    fn used_ids(&self, entry_point: &Instruction) -> HashSet<Word> {
+        let functions: HashMap<Word, &rspirv::dr::Function> = self
+            .module
+            .functions
+            .iter()
+            .filter_map(|function| Some((function.def.as_ref()?.result_id?, function)))
+            .collect();
+
+        let mut used = HashSet::new();
+        let mut pending: Vec<Word> = entry_point
+            .operands
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::IdRef(id) => Some(*id),
+                _ => None,
+            })
+            .collect();
+        while let Some(id) = pending.pop() {
+            if !used.insert(id) {
+                continue;
+            }
+            let instructions: Vec<&Instruction> = match functions.get(&id) {
+                Some(function) => function.all_inst_iter().collect(),
+                None => self.definitions.get(&id).into_iter().copied().collect(),
+            };
+            for inst in instructions {
+                pending.extend(inst.result_type);
+                for operand in &inst.operands {
+                    if let Operand::IdRef(id) = operand {
+                        pending.push(*id);
+                    }
+                }
+            }
+        }
+        used
+    }
+
+    fn entry_point(&self, inst: &Instruction) -> MainResult<EntryPoint> {
+        let (execution_model, function, name) = match inst.operands.as_slice() {
+            [Operand::ExecutionModel(model), Operand::IdRef(function), Operand::LiteralString(name), ..] => {
+                (format!("{:?}", model), *function, name.clone())
+            }
+            _ => return Err("malformed OpEntryPoint".into()),
+        };
+
+        let mut execution_modes = Vec::new();
+        let mut local_size = None;
+        for mode_inst in &self.module.execution_modes {
+            let [Operand::IdRef(target), Operand::ExecutionMode(mode), operands @ ..] =
+                mode_inst.operands.as_slice()
+            else {
+                continue;
+            };
+            if *target != function {
+                continue;
+            }
+            let operands: Vec<u32> = operands
+                .iter()
+                .filter_map(|operand| match operand {
+                    Operand::LiteralInt32(value) => Some(*value),
+                    Operand::IdRef(id) => self.constant_u32(*id),
+                    _ => None,
+                })
+                .collect();
+            let mode = format!("{:?}", mode);
+            if let ("LocalSize" | "LocalSizeId", [x, y, z]) = (mode.as_str(), operands.as_slice()) {
+                local_size = Some([*x, *y, *z]);
+            }
+            execution_modes.push(ExecutionMode { mode, operands });
+        }
+
+        let used = self.used_ids(inst);
+        let mut entry_point = EntryPoint {
+            name,
+            execution_model,
+            execution_modes,
+            local_size,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            bindings: Vec::new(),
+            push_constants: Vec::new(),
+            specialization_constants: Vec::new(),
+        };
+
+        for global in &self.module.types_global_values {
+            let Some(id) = global.result_id else {
+                continue;
+            };
+            if !used.contains(&id) {
+                continue;
+            }
+            match global.class.opcode {
+                Op::Variable => self.add_variable(&mut entry_point, global, id),
+                Op::SpecConstant | Op::SpecConstantTrue | Op::SpecConstantFalse => {
+                    if let Some(spec_id) = self.decoration_u32(id, Decoration::SpecId) {
+                        entry_point
+                            .specialization_constants
+                            .push(self.specialization_constant(global, id, spec_id));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        entry_point
+            .bindings
+            .sort_by_key(|binding| (binding.set, binding.binding));
+        entry_point.inputs.sort_by_key(|input| input.location);
+        entry_point.outputs.sort_by_key(|output| output.location);
+        entry_point
+            .specialization_constants
+            .sort_by_key(|constant| constant.id);
+        Ok(entry_point)
+    }
+
+    fn add_variable(&self, entry_point: &mut EntryPoint, var: &Instruction, id: Word) {
+        let Some(Operand::StorageClass(storage_class)) = var.operands.first() else {
+            return;
+        };
+        let Some(ty) = var
+            .result_type
+            .and_then(|ptr| self.definitions.get(&ptr))
+            .and_then(|ptr| Self::id_operand(ptr, 1))
+        else {
+            return;
+        };
+
+        match storage_class {
+            StorageClass::Input | StorageClass::Output => {
+                let variable = Variable {
+                    name: self.name(id),
+                    location: self.decoration_u32(id, Decoration::Location),
+                    builtin: self.builtin(id),
+                    ty: self.type_name(ty),
+                };
+                if *storage_class == StorageClass::Input {
+                    entry_point.inputs.push(variable);
+                } else {
+                    entry_point.outputs.push(variable);
+                }
+            }
+            StorageClass::PushConstant => {
+                let struct_inst = self.definitions.get(&ty);
+                let members = struct_inst.map_or(0, |inst| inst.operands.len() as u32);
+                let offset = (0..members)
+                    .filter_map(|member| self.member_decoration_u32(ty, member, Decoration::Offset))
+                    .min()
+                    .unwrap_or(0);
+                let end = self.type_size(ty).unwrap_or(offset);
+                entry_point.push_constants.push(PushConstantRange {
+                    name: self.name(id),
+                    ty: self.type_name(ty),
+                    offset,
+                    size: end - offset,
+                });
+            }
+            StorageClass::Uniform | StorageClass::UniformConstant | StorageClass::StorageBuffer => {
+                let (Some(set), Some(binding)) = (
+                    self.decoration_u32(id, Decoration::DescriptorSet),
+                    self.decoration_u32(id, Decoration::Binding),
+                ) else {
+                    return;
+                };
+
+                // Arrays of resources are arrays of descriptors.
+                let (element, count) = match self.definitions.get(&ty) {
+                    Some(inst) if inst.class.opcode == Op::TypeArray => (
+                        Self::id_operand(inst, 0).unwrap_or(ty),
+                        Self::id_operand(inst, 1).and_then(|id| self.constant_u32(id)),
+                    ),
+                    Some(inst) if inst.class.opcode == Op::TypeRuntimeArray => {
+                        (Self::id_operand(inst, 0).unwrap_or(ty), None)
+                    }
+                    _ => (ty, Some(1)),
+                };
+
+                let descriptor_type = self.descriptor_type(*storage_class, element);
+                let size = match descriptor_type {
+                    "uniform_buffer" | "storage_buffer" => self.type_size(element),
+                    _ => None,
+                };
+                entry_point.bindings.push(Binding {
+                    set,
+                    binding,
+                    name: self.name(id),
+                    descriptor_type: descriptor_type.into(),
+                    count,
+                    ty: self.type_name(element),
+                    size,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    // The Vulkan descriptor type of a resource, in snake case.
+    fn descriptor_type(&self, storage_class: StorageClass, ty: Word) -> &'static str {
+        let Some(inst) = self.definitions.get(&ty) else {
+            return "unknown";
+        };
+        match (storage_class, inst.class.opcode) {
+            (StorageClass::StorageBuffer, _) => "storage_buffer",
+            (StorageClass::Uniform, _)
+                if self.decoration(ty, Decoration::BufferBlock).is_some() =>
+            {
+                "storage_buffer"
+            }
+            (StorageClass::Uniform, _) => "uniform_buffer",
+            (_, Op::TypeSampler) => "sampler",
+            (_, Op::TypeSampledImage) => "combined_image_sampler",
+            (_, Op::TypeAccelerationStructureKHR) => "acceleration_structure",
+            (_, Op::TypeImage) => {
+                let dim = match inst.operands.get(1) {
+                    Some(Operand::Dim(dim)) => Some(*dim),
+                    _ => None,
+                };
+                let sampled = Self::literal_operand(inst, 5);
+                match (dim, sampled) {
+                    (Some(Dim::DimSubpassData), _) => "input_attachment",
+                    (Some(Dim::DimBuffer), Some(2)) => "storage_texel_buffer",
+                    (Some(Dim::DimBuffer), _) => "uniform_texel_buffer",
+                    (_, Some(2)) => "storage_image",
+                    _ => "sampled_image",
+                }
+            }
+            _ => "unknown",
+        }
+    }
+
+    fn specialization_constant(
+        &self,
+        inst: &Instruction,
+        id: Word,
+        spec_id: u32,
+    ) -> SpecializationConstant {
+        let ty = inst
+            .result_type
+            .map(|ty| self.type_name(ty))
+            .unwrap_or_default();
+        let default = match (inst.class.opcode, inst.operands.first()) {
+            (Op::SpecConstantTrue, _) => true.into(),
+            (Op::SpecConstantFalse, _) => false.into(),
+            (_, Some(Operand::LiteralInt32(value))) if ty.starts_with('i') => {
+                (*value as i32).into()
+            }
+            (_, Some(Operand::LiteralInt32(value))) => (*value).into(),
+            (_, Some(Operand::LiteralInt64(value))) if ty.starts_with('i') => {
+                (*value as i64).into()
+            }
+            (_, Some(Operand::LiteralInt64(value))) => (*value).into(),
+            (_, Some(Operand::LiteralFloat32(value))) => (*value).into(),
+            (_, Some(Operand::LiteralFloat64(value))) => (*value).into(),
+            _ => serde_json::Value::Null,
+        };
+        SpecializationConstant {
+            id: spec_id,
+            name: self.name(id),
+            ty,
+            default,
+        }
+    }
+}
+
+#[test]
+fn test_reflect() {
+    use crate::test_util::{assemble_with_entry_point, shader_builder};
+    use rspirv::spirv::{BuiltIn, ExecutionMode, ExecutionModel};
+
+    let mut b = shader_builder();
+    let uint = b.type_int(32, 0);
+    let float = b.type_float(32);
+    let v3uint = b.type_vector(uint, 3);
+    let v4float = b.type_vector(float, 4);
+
+    let params = b.type_struct([v4float, float]);
+    b.name(params, "Params");
+    b.decorate(params, Decoration::Block, []);
+    b.member_decorate(params, 0, Decoration::Offset, [Operand::LiteralInt32(0)]);
+    b.member_decorate(params, 1, Decoration::Offset, [Operand::LiteralInt32(16)]);
+    let params_ptr = b.type_pointer(None, StorageClass::Uniform, params);
+    let params_var = b.variable(params_ptr, None, StorageClass::Uniform, None);
+    b.name(params_var, "params");
+    b.decorate(
+        params_var,
+        Decoration::DescriptorSet,
+        [Operand::LiteralInt32(1)],
+    );
+    b.decorate(params_var, Decoration::Binding, [Operand::LiteralInt32(2)]);
+
+    // Not used by the entry point.
+    let unused_var = b.variable(params_ptr, None, StorageClass::Uniform, None);
+    b.decorate(
+        unused_var,
+        Decoration::DescriptorSet,
+        [Operand::LiteralInt32(0)],
+    );
+    b.decorate(unused_var, Decoration::Binding, [Operand::LiteralInt32(0)]);
+
+    let push = b.type_struct([float, float]);
+    b.decorate(push, Decoration::Block, []);
+    b.member_decorate(push, 0, Decoration::Offset, [Operand::LiteralInt32(8)]);
+    b.member_decorate(push, 1, Decoration::Offset, [Operand::LiteralInt32(12)]);
+    let push_ptr = b.type_pointer(None, StorageClass::PushConstant, push);
+    let push_var = b.variable(push_ptr, None, StorageClass::PushConstant, None);
+
+    let id_ptr = b.type_pointer(None, StorageClass::Input, v3uint);
+    let id_var = b.variable(id_ptr, None, StorageClass::Input, None);
+    b.decorate(
+        id_var,
+        Decoration::BuiltIn,
+        [Operand::BuiltIn(BuiltIn::GlobalInvocationId)],
+    );
+
+    let count = b.spec_constant_u32(uint, 7);
+    b.decorate(count, Decoration::SpecId, [Operand::LiteralInt32(3)]);
+    b.name(count, "COUNT");
+
+    let zero = b.constant_u32(uint, 0);
+    let float_uniform_ptr = b.type_pointer(None, StorageClass::Uniform, float);
+    let float_push_ptr = b.type_pointer(None, StorageClass::PushConstant, float);
+    let one = b.constant_u32(uint, 1);
+    let bytes = assemble_with_entry_point(
+        b,
+        ExecutionModel::GLCompute,
+        "main_cs",
+        &[id_var],
+        |b, main| {
+            let src = b
+                .access_chain(float_push_ptr, None, push_var, [zero])
+                .unwrap();
+            let value = b.load(float, None, src, None, []).unwrap();
+            let dst = b
+                .access_chain(float_uniform_ptr, None, params_var, [one])
+                .unwrap();
+            b.store(dst, value, None, []).unwrap();
+            let _ = b.i_add(uint, None, count, zero).unwrap();
+            b.execution_mode(main, ExecutionMode::LocalSize, [8, 4, 1]);
+        },
+    );
+    let reflection = reflect(&[bytes]).unwrap();
+    let json = serde_json::to_value(&reflection).unwrap();
+
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "entry_points": [{
+                "name": "main_cs",
+                "execution_model": "GLCompute",
+                "execution_modes": [{"mode": "LocalSize", "operands": [8, 4, 1]}],
+                "local_size": [8, 4, 1],
+                "inputs": [{"name": null, "location": null, "builtin": "GlobalInvocationId", "type": "vec3<u32>"}],
+                "outputs": [],
+                "bindings": [{
+                    "set": 1,
+                    "binding": 2,
+                    "name": "params",
+                    "descriptor_type": "uniform_buffer",
+                    "count": 1,
+                    "type": "Params",
+                    "size": 20,
+                }],
+                "push_constants": [{"name": null, "type": "struct", "offset": 8, "size": 8}],
+                "specialization_constants": [{"id": 3, "name": "COUNT", "type": "u32", "default": 7}],
+            }]
+        })
+    );
+}
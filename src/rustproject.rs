@@ -1,42 +1,41 @@
-// From https://gist.github.com/MiSawa/6e9e3c400803e7f4ae4d190d4477b4b8
+/*!
+Generation of a `rust-project.json` describing the packages of shaders to rust-analyzer, used by `ide-setup`.
+
+The crate graph is taken from `cargo metadata` on the generated packages, and the sysroot is the one of the bundled toolchain. Proc macros are built with the bundled toolchain, so that rust-analyzer can expand them.
+*/
+// Originally from https://gist.github.com/MiSawa/6e9e3c400803e7f4ae4d190d4477b4b8
 // Context: https://github.com/fornwall/rust-script/issues/11#issuecomment-1516408804
-use std::{collections::{HashMap, HashSet}, io::Write, path::{Path, PathBuf}};
 
-use clap::Parser;
-use project_model::{CargoConfig, ProjectManifest, ProjectWorkspace, RustLibSource, Sysroot};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::error::MainResult;
 
 #[derive(Serialize, Debug)]
-struct RustProject {
+pub struct RustProject {
     sysroot: PathBuf,
     sysroot_src: PathBuf,
     crates: Vec<Crate>,
+    /// The index of the crate of each package added, by package id, as packages share dependencies such as spirv-std.
+    #[serde(skip)]
+    crate_indices: HashMap<String, usize>,
 }
+
 #[derive(Serialize, Debug)]
 struct Crate {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(default)]
-    display_name: Option<String>,
+    display_name: String,
     root_module: PathBuf,
     edition: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(default)]
-    version: Option<String>,
+    version: String,
     deps: Vec<Dep>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    #[serde(default)]
     cfg: Vec<String>,
-
+    is_workspace_member: bool,
     is_proc_macro: bool,
-
     #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(default)]
     proc_macro_dylib_path: Option<PathBuf>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(default)]
-    repository: Option<String>,
 }
+
 #[derive(Serialize, Debug)]
 struct Dep {
     #[serde(rename = "crate")]
@@ -44,141 +43,286 @@ struct Dep {
     name: String,
 }
 
-fn edit_project(shader_path: &Path, project_dir: PathBuf) -> Result<()> {
-    let sysroot = Sysroot::discover(&project_dir, &Default::default()).unwrap();
-
-    let manifest = ProjectManifest::from_manifest_file(project_dir.join("Cargo.toml")).unwrap();
-
-    let mut config = CargoConfig::default();
-    config.sysroot = Some(RustLibSource::Path(sysroot.root().to_path_buf()));
-    config.rustc_source = Some(RustLibSource::Discover);
-
-    let ws = ProjectWorkspace::load(manifest, &config, &|_| {}).unwrap();
-
-    let mut path_to_id = HashMap::new();
-    let mut id_to_path = HashMap::new();
-    let (crate_graph, proc_macro_paths) = ws.to_crate_graph(
-        &mut |path| {
-            let path = if path.parent() == Some(&project_dir) {
-                // Translate the script path!
-                shader_path.clone()
-            } else {
-                path.to_owned()
-            };
-            let n = path_to_id.len() as u32;
-            let ret = vfs::FileId(path_to_id.entry(path.to_owned()).or_insert(n).clone());
-            id_to_path
-                .entry(ret.clone())
-                .or_insert_with(|| path.to_owned());
-            Some(ret)
-        },
-        &Default::default(),
-    );
+// The output of `cargo metadata`, as far as it's needed.
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    resolve: Resolve,
+}
 
-    let mut crate_ids = vec![];
-    let mut crate_id_to_index = HashMap::new();
-    for id in crate_graph.iter() {
-        crate_ids.push(id);
-        crate_id_to_index.insert(id, crate_id_to_index.len());
-    }
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    name: String,
+    version: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    kind: Vec<String>,
+    src_path: PathBuf,
+    edition: String,
+}
+
+#[derive(Deserialize)]
+struct Resolve {
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    id: String,
+    deps: Vec<NodeDep>,
+    features: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct NodeDep {
+    name: String,
+    pkg: String,
+    dep_kinds: Vec<DepKind>,
+}
+
+#[derive(Deserialize)]
+struct DepKind {
+    kind: Option<String>,
+}
+
+/// The kinds of targets which can be depended on.
+const LIBRARY_KINDS: &[&str] = &["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"];
+
+fn parse_metadata(metadata: &str) -> MainResult<Metadata> {
+    serde_json::from_str(metadata)
+        .map_err(|e| format!("malformed output of cargo metadata: {}", e).into())
+}
 
-    let cfg_keys_to_ignore: HashSet<&'static str> = HashSet::from_iter([
-        "debug_assertions",
-        "panic",
-        "target_abi",
-        "target_arch",
-        "target_endian",
-        "target_env",
-        "target_family",
-        "target_feature",
-        "target_has_atomic",
-        "target_has_atomic_equal_alignment",
-        "target_has_atomic_load_store",
-        "target_os",
-        "target_pointer_width",
-        "target_thread_local",
-        "target_vendor",
-        "unix",
-        "windows",
-    ]);
-
-    let mut crates = vec![];
-    for id in crate_ids {
-        let data = &crate_graph[id];
-        let display_name = data
-            .display_name
-            .as_ref()
-            .map(|name| name.canonical_name().to_owned());
-
-        let root_module = id_to_path[&data.root_file_id].clone().into();
-        let edition = match data.edition {
-            base_db::Edition::Edition2015 => "2015",
-            base_db::Edition::Edition2018 => "2018",
-            base_db::Edition::Edition2021 => "2021",
+fn is_proc_macro(target: &Target) -> bool {
+    target.kind.iter().any(|kind| kind == "proc-macro")
+}
+
+/**
+The proc macro packages in the output of `cargo metadata`, as specs such as `spirv-std-macros@0.9.0` for building them.
+*/
+pub fn proc_macro_packages(metadata: &str) -> MainResult<Vec<String>> {
+    Ok(parse_metadata(metadata)?
+        .packages
+        .iter()
+        .filter(|package| package.targets.iter().any(is_proc_macro))
+        .map(|package| format!("{}@{}", package.name, package.version))
+        .collect())
+}
+
+impl RustProject {
+    /**
+    An empty project using the sysroot of the given toolchain.
+    */
+    pub fn new(toolchain_path: &Path) -> Self {
+        Self {
+            sysroot: toolchain_path.to_owned(),
+            sysroot_src: toolchain_path.join("lib/rustlib/src/rust/library"),
+            crates: Vec::new(),
+            crate_indices: HashMap::new(),
         }
-        .to_owned();
-        let deps = data
-            .dependencies
+    }
+
+    /**
+    Add the crates of a package, given the output of `cargo metadata` on it and the built proc macro libraries by package id. Crates added for an earlier package are shared.
+
+    The crates a shader depends on are given `target_arch = "spirv"`, as they are compiled with it, while proc macros, build dependencies and their dependencies are compiled for the host.
+    */
+    pub fn add_package(
+        &mut self,
+        metadata: &str,
+        proc_macro_dylibs: &HashMap<String, PathBuf>,
+    ) -> MainResult<()> {
+        let metadata = parse_metadata(metadata)?;
+        let nodes: HashMap<&str, &Node> = metadata
+            .resolve
+            .nodes
             .iter()
-            .map(|dep| Dep {
-                krate: crate_id_to_index[&dep.crate_id],
-                name: dep.name.to_string(),
-            })
+            .map(|node| (node.id.as_str(), node))
             .collect();
-        let cfg: Vec<_> = data
-            .cfg_options
-            .get_cfg_keys()
-            .filter(|key| !cfg_keys_to_ignore.contains(key.as_str()))
-            .flat_map(|key| {
-                data.cfg_options
-                    .check(&cfg::CfgExpr::Atom(cfg::CfgAtom::Flag(key.clone())))
-                    .filter(|x| *x)
-                    .map(|_| format!("{key}"))
-                    .into_iter()
-                    .chain(
-                        data.cfg_options
-                            .get_cfg_values(key)
-                            // TODO: Escape?
-                            .map(move |value| format!(r#"{key}="{value}""#)),
-                    )
+
+        let libraries: Vec<(&Package, &Target)> = metadata
+            .packages
+            .iter()
+            .filter(|package| !self.crate_indices.contains_key(&package.id))
+            .filter_map(|package| {
+                let target = package.targets.iter().find(|target| {
+                    target
+                        .kind
+                        .iter()
+                        .any(|kind| LIBRARY_KINDS.contains(&kind.as_str()))
+                })?;
+                Some((package, target))
             })
             .collect();
-        let proc_macro_dylib_path = proc_macro_paths
-            .get(&id)
-            .and_then(|v| v.as_ref().ok().map(|v| v.1.clone().into()));
-        let repository = match &data.origin {
-            base_db::CrateOrigin::Local { repo, .. }
-            | base_db::CrateOrigin::Library { repo, .. } => repo.clone(),
-            _ => None,
-        };
-
-        crates.push(Crate {
-            display_name,
-            root_module,
-            edition,
-            version: data.version.clone(),
-            deps,
-            cfg,
-            is_proc_macro: data.is_proc_macro,
-            proc_macro_dylib_path,
-            repository,
-        });
+        for (idx, (package, _)) in libraries.iter().enumerate() {
+            self.crate_indices
+                .insert(package.id.clone(), self.crates.len() + idx);
+        }
+
+        let proc_macros: HashSet<&str> = libraries
+            .iter()
+            .filter(|(_, target)| is_proc_macro(target))
+            .map(|(package, _)| package.id.as_str())
+            .collect();
+        // The crates compiled for the shader target: those the workspace members reach through normal dependencies, short of proc macros.
+        let mut spirv_crates = HashSet::new();
+        let mut queue: Vec<&str> = metadata
+            .workspace_members
+            .iter()
+            .map(String::as_str)
+            .collect();
+        while let Some(id) = queue.pop() {
+            if proc_macros.contains(id) || !spirv_crates.insert(id) {
+                continue;
+            }
+            if let Some(node) = nodes.get(id) {
+                queue.extend(
+                    node.deps
+                        .iter()
+                        .filter(|dep| dep.dep_kinds.iter().any(|kind| kind.kind.is_none()))
+                        .map(|dep| dep.pkg.as_str()),
+                );
+            }
+        }
+
+        for (package, target) in libraries {
+            let node = nodes.get(package.id.as_str());
+            let deps = node
+                .map(|node| node.deps.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .filter(|dep| dep.dep_kinds.iter().any(|kind| kind.kind.is_none()))
+                .filter_map(|dep| {
+                    Some(Dep {
+                        krate: *self.crate_indices.get(&dep.pkg)?,
+                        name: dep.name.clone(),
+                    })
+                })
+                .collect();
+
+            let mut cfg: Vec<String> = node
+                .map(|node| node.features.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .map(|feature| format!("feature=\"{}\"", feature))
+                .collect();
+            if spirv_crates.contains(package.id.as_str()) {
+                cfg.push("target_arch=\"spirv\"".into());
+            }
+
+            self.crates.push(Crate {
+                display_name: package.name.clone(),
+                root_module: target.src_path.clone(),
+                edition: target.edition.clone(),
+                version: package.version.clone(),
+                deps,
+                cfg,
+                is_workspace_member: metadata.workspace_members.contains(&package.id),
+                is_proc_macro: is_proc_macro(target),
+                proc_macro_dylib_path: proc_macro_dylibs.get(&package.id).cloned(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> MainResult<String> {
+        Ok(serde_json::to_string_pretty(self).map_err(|e| e.to_string())? + "\n")
     }
+}
 
-    let project = RustProject {
-        sysroot: sysroot.root().to_owned().into(),
-        sysroot_src: sysroot.src_root().to_owned().into(),
-        crates,
+#[test]
+fn test_add_package() {
+    // The metadata of the package of a shader, depending on spirv-std, whose macros depend on syn, and on cc to build.
+    let metadata = |shader: &str| {
+        format!(
+            r#"{{
+            "packages": [
+                {{"id": "{shader} 0.1.0", "name": "{shader}", "version": "0.1.0", "targets": [
+                    {{"kind": ["dylib"], "src_path": "/shaders/{shader}.rs", "edition": "2021"}}
+                ]}},
+                {{"id": "spirv-std 0.9.0", "name": "spirv-std", "version": "0.9.0", "targets": [
+                    {{"kind": ["lib"], "src_path": "/spirv-std/src/lib.rs", "edition": "2021"}}
+                ]}},
+                {{"id": "spirv-std-macros 0.9.0", "name": "spirv-std-macros", "version": "0.9.0", "targets": [
+                    {{"kind": ["proc-macro"], "src_path": "/spirv-std-macros/src/lib.rs", "edition": "2021"}}
+                ]}},
+                {{"id": "syn 2.0.0", "name": "syn", "version": "2.0.0", "targets": [
+                    {{"kind": ["lib"], "src_path": "/syn/src/lib.rs", "edition": "2021"}}
+                ]}},
+                {{"id": "cc 1.0.0", "name": "cc", "version": "1.0.0", "targets": [
+                    {{"kind": ["lib"], "src_path": "/cc/src/lib.rs", "edition": "2018"}}
+                ]}}
+            ],
+            "workspace_members": ["{shader} 0.1.0"],
+            "resolve": {{"nodes": [
+                {{"id": "{shader} 0.1.0", "features": [], "deps": [
+                    {{"name": "spirv_std", "pkg": "spirv-std 0.9.0", "dep_kinds": [{{"kind": null}}]}},
+                    {{"name": "cc", "pkg": "cc 1.0.0", "dep_kinds": [{{"kind": "build"}}]}}
+                ]}},
+                {{"id": "spirv-std 0.9.0", "features": ["glam"], "deps": [
+                    {{"name": "spirv_std_macros", "pkg": "spirv-std-macros 0.9.0", "dep_kinds": [{{"kind": null}}]}}
+                ]}},
+                {{"id": "spirv-std-macros 0.9.0", "features": [], "deps": [
+                    {{"name": "syn", "pkg": "syn 2.0.0", "dep_kinds": [{{"kind": null}}]}}
+                ]}},
+                {{"id": "syn 2.0.0", "features": [], "deps": []}},
+                {{"id": "cc 1.0.0", "features": [], "deps": []}}
+            ]}}
+        }}"#
+        )
     };
 
-    let json = serde_json::to_string_pretty(&project).expect("unable to serialize to json");
-    let rust_project_json_path= project_dir.join("rust-project.json");
-    let mut rust_project_json_file = std::fs::OpenOptions::new()
-        .create(true) // To create a new file
-        .write(true)
-        .open(rust_project_json_path)
-        .expect("Could not open rust-project.json");
-    rust_project_json_file.write_all(json.as_bytes());
+    assert_eq!(
+        proc_macro_packages(&metadata("first")).unwrap(),
+        ["spirv-std-macros@0.9.0"]
+    );
 
-    Ok(())
+    let dylibs = HashMap::from([(
+        "spirv-std-macros 0.9.0".to_owned(),
+        PathBuf::from("/target/release/deps/libspirv_std_macros.so"),
+    )]);
+    let mut project = RustProject::new(Path::new("/toolchain"));
+    project.add_package(&metadata("first"), &dylibs).unwrap();
+    project.add_package(&metadata("second"), &dylibs).unwrap();
+    let names: Vec<&str> = project
+        .crates
+        .iter()
+        .map(|krate| krate.display_name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "first",
+            "spirv-std",
+            "spirv-std-macros",
+            "syn",
+            "cc",
+            "second"
+        ]
+    );
+
+    let second = &project.crates[5];
+    assert!(second.is_workspace_member);
+    assert_eq!(second.deps.len(), 1);
+    assert_eq!(second.deps[0].krate, 1);
+    assert_eq!(second.deps[0].name, "spirv_std");
+    assert_eq!(second.cfg, ["target_arch=\"spirv\""]);
+    assert_eq!(
+        project.crates[1].cfg,
+        ["feature=\"glam\"", "target_arch=\"spirv\""]
+    );
+    assert!(project.crates[2].is_proc_macro);
+    assert_eq!(
+        project.crates[2].proc_macro_dylib_path,
+        Some(PathBuf::from("/target/release/deps/libspirv_std_macros.so"))
+    );
+    assert_eq!(project.crates[1].proc_macro_dylib_path, None);
+    assert!(project.crates[2].cfg.is_empty());
+    assert!(project.crates[3].cfg.is_empty());
+    assert!(project.crates[4].cfg.is_empty());
 }
@@ -0,0 +1,323 @@
+/*!
+A compile server taking requests over a Unix socket, used by `serve`.
+
+Each line sent to the socket is a JSON request, answered by a line for each compiler diagnostic as it is emitted and a final line with the outcome. The generated packages and the records of their last builds are kept in memory between requests, up to a limit, and builds are run one at a time. Responses are written to the client independently of the build, so that a slow client doesn't hold up the builds of others.
+*/
+
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::time::{Instant, SystemTime};
+
+use crate::arguments::{self, Args};
+use crate::compiler::CompileOptions;
+use crate::error::{Error, MainError, MainResult};
+use crate::fingerprint::{self, BuildRecord};
+use crate::{
+    clean_cache, consts, generate_package, prepare_action, read_script, Input, InputAction,
+};
+
+/**
+A compile request, as sent by a client.
+
+Paths are relative to the working directory of the server.
+*/
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    /// Echoed back in the responses to the request.
+    #[serde(default)]
+    id: serde_json::Value,
+    shader: String,
+    output: Option<String>,
+    target: Option<String>,
+    emit: Option<String>,
+    #[serde(default)]
+    debug: bool,
+    #[serde(default)]
+    multimodule: bool,
+    #[serde(default)]
+    validate: bool,
+    #[serde(default)]
+    capabilities: Vec<String>,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    codegen_flags: Vec<String>,
+}
+
+impl Request {
+    // The command line arguments the request corresponds to.
+    fn args(&self) -> MainResult<Args> {
+        if self.shader == "-" || self.output.as_deref() == Some("-") {
+            return Err("standard input and output cannot be used by the server".into());
+        }
+
+        let mut options = CompileOptions::new()
+            .debug(self.debug)
+            .multimodule(self.multimodule)
+            .validate(self.validate);
+        if let Some(target) = &self.target {
+            options = options.target(target);
+        }
+        for capability in &self.capabilities {
+            options = options.capability(capability);
+        }
+        for extension in &self.extensions {
+            options = options.extension(extension);
+        }
+        for flag in &self.codegen_flags {
+            options = options.codegen_flag(flag);
+        }
+
+        let mut args = options.args()?;
+        if let Some(emit) = &self.emit {
+            if !arguments::EMIT_KINDS.contains(&emit.as_str()) {
+                return Err(format!("unknown emit kind '{}'", emit).into());
+            }
+            args.emit = emit.clone();
+        }
+        args.output_path = self.output.clone();
+        args.scripts = vec![self.shader.clone()];
+        Ok(args)
+    }
+}
+
+/**
+A package prepared for an earlier request, which is built again as-is while its input is unchanged.
+*/
+struct Package {
+    input: Input,
+    action: InputAction,
+    /// The record of the last build, with the modification time of its file after the build.
+    last_build: Option<(Option<SystemTime>, BuildRecord)>,
+    /// When the package was last requested, for dropping the least recently used packages.
+    last_used: Instant,
+}
+
+/**
+Listen for compile requests on the Unix socket at the given path, until the process is killed.
+*/
+pub fn serve(socket_path: &Path) -> MainResult<i32> {
+    if let Ok(metadata) = fs::symlink_metadata(socket_path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{} exists and is not a socket", socket_path.display()).into());
+        }
+        // A socket left behind by a server which was killed is replaced, but not one of a running server.
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(
+                format!("a server is already listening on {}", socket_path.display()).into(),
+            );
+        }
+        fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path).map_err(|e| {
+        MainError::Tag(
+            format!("cannot listen on {}", socket_path.display()).into(),
+            Box::new(e.into()),
+        )
+    })?;
+
+    // The cache is cleaned out once, rather than after each build.
+    if let Err(err) = clean_cache(consts::MAX_CACHE_AGE_MS) {
+        error!("failed to clean cache: {}", err);
+    }
+    eprintln!("listening on {}", socket_path.display());
+
+    let packages = Mutex::new(HashMap::new());
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    error!("failed to accept connection: {}", err);
+                    continue;
+                }
+            };
+            let packages = &packages;
+            scope.spawn(move || {
+                if let Err(err) = handle_connection(stream, packages) {
+                    info!("connection closed: {}", err);
+                }
+            });
+        }
+    });
+    Ok(0)
+}
+
+// Answer the requests of a client until it disconnects.
+fn handle_connection(
+    stream: UnixStream,
+    packages: &Mutex<HashMap<String, Package>>,
+) -> MainResult<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Request = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                let error = format!("invalid request: {}", err);
+                send(&mut writer, json!({ "success": false, "error": error }))?;
+                continue;
+            }
+        };
+        info!("request: {:?}", request);
+
+        // The diagnostics are buffered in the channel, so that the build, and with it the lock, doesn't wait for the client.
+        let (sender, receiver) = mpsc::channel();
+        let (result, sent) = std::thread::scope(|scope| -> MainResult<_> {
+            let build = scope.spawn(|| {
+                // Holding the lock for the build keeps builds from competing for the target directory.
+                let mut packages = packages.lock().unwrap_or_else(PoisonError::into_inner);
+                compile(&mut packages, &request, sender).map_err(Error::from)
+            });
+            let mut sent = 0;
+            for diagnostic in receiver {
+                send(
+                    &mut writer,
+                    json!({ "id": request.id, "diagnostic": diagnostic }),
+                )?;
+                sent += 1;
+            }
+            let result = build
+                .join()
+                .unwrap_or_else(|_| Err(MainError::from("the build panicked").into()));
+            Ok((result, sent))
+        })?;
+
+        let response = match result {
+            Ok(mut response) => {
+                response["id"] = request.id.clone();
+                response
+            }
+            Err(err) => {
+                // Such as the errors of cargo itself, which are only known when it exits.
+                for diagnostic in err.diagnostics().iter().skip(sent) {
+                    send(
+                        &mut writer,
+                        json!({ "id": request.id, "diagnostic": diagnostic }),
+                    )?;
+                }
+                json!({ "id": request.id, "success": false, "error": err.message() })
+            }
+        };
+        send(&mut writer, response)?;
+    }
+    Ok(())
+}
+
+// Write a response line to the client.
+fn send(writer: &mut UnixStream, response: serde_json::Value) -> MainResult<()> {
+    writeln!(writer, "{}", response)?;
+    Ok(())
+}
+
+/**
+Build the shader of a request, sending the diagnostics to the sender as they are emitted.
+
+The package of an earlier request with the same arguments is reused if its input is unchanged.
+*/
+fn compile(
+    packages: &mut HashMap<String, Package>,
+    request: &Request,
+    sender: mpsc::Sender<String>,
+) -> MainResult<serde_json::Value> {
+    let args = request.args()?;
+    let input = read_script(&request.shader)?;
+    let key = format!("{:?}", args);
+
+    let mut package = match packages.remove(&key) {
+        Some(mut package) if package.input == input => {
+            package.action.lock()?;
+            // The package may have been pruned from the cache since.
+            if !package.action.existing_crate && !package.action.manifest_path().is_file() {
+                generate_package(&package.action)?;
+            }
+            package
+        }
+        _ => Package {
+            action: prepare_action(&input, &args)?,
+            input,
+            last_build: None,
+            last_used: Instant::now(),
+        },
+    };
+    package.last_used = Instant::now();
+
+    package.action.capture_diagnostics = true;
+    package.action.diagnostics_sender = Some(sender);
+    let result = build(&mut package);
+    package.action.diagnostics_sender = None;
+    package.action.locks.clear();
+    packages.insert(key, package);
+    while packages.len() > consts::SERVE_MAX_PACKAGES {
+        let least_recently_used = packages
+            .iter()
+            .min_by_key(|(_, package)| package.last_used)
+            .map(|(key, _)| key.clone())
+            .unwrap();
+        packages.remove(&least_recently_used);
+    }
+    result
+}
+
+// Build a package, and write its output.
+fn build(package: &mut Package) -> MainResult<serde_json::Value> {
+    let action = &package.action;
+    let modified = || {
+        fs::metadata(&action.fingerprint_path)
+            .and_then(|md| md.modified())
+            .ok()
+    };
+
+    // The record is loaded again if another invocation has built the package since.
+    let previous_build = match package.last_build.take() {
+        Some((Some(mtime), record)) if modified() == Some(mtime) => Some(record),
+        _ => fingerprint::load(&action.fingerprint_path),
+    };
+
+    let build = action.build_from(previous_build)?;
+    action.validate(&build.compile_result)?;
+    let outputs = action.write_output(&build.compile_result)?;
+
+    let response = json!({
+        "success": true,
+        "entry_points": build.compile_result.entry_points,
+        "outputs": outputs,
+        "dependencies": build.dependencies,
+    });
+    let record = BuildRecord {
+        fingerprint: build.fingerprint,
+        dependencies: build.dependencies,
+        compile_result: build.compile_result,
+    };
+    package.last_build = Some((modified(), record));
+    Ok(response)
+}
+
+#[test]
+fn test_request_args() {
+    let request: Request = serde_json::from_str(
+        r#"{"id": 7, "shader": "shader.rs", "emit": "wgsl", "codegen_flags": ["relax-block-layout"]}"#,
+    )
+    .unwrap();
+    let args = request.args().unwrap();
+    assert_eq!(args.scripts, ["shader.rs"]);
+    assert_eq!(args.emit, "wgsl");
+    assert_eq!(args.codegen_flags, ["relax-block-layout"]);
+    assert_eq!(args.target, "spirv-unknown-vulkan1.1");
+
+    let request: Request = serde_json::from_str(r#"{"shader": "-"}"#).unwrap();
+    assert!(request.args().is_err());
+    assert!(serde_json::from_str::<Request>(r#"{"shader": "a.rs", "bogus": 1}"#).is_err());
+}
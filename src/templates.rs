@@ -0,0 +1,69 @@
+/*!
+Templates for turning expressions given on the command line into complete shader scripts.
+*/
+
+use crate::error::MainResult;
+
+/// The shader stages an expression can be wrapped into.
+pub const STAGES: &[&str] = &["fragment", "vertex", "compute"];
+
+const PRELUDE: &str = r#"#![no_std]
+#![allow(unused_imports, unused_variables)]
+
+use spirv_std::glam::*;
+use spirv_std::num_traits::Float;
+use spirv_std::spirv;
+"#;
+
+/**
+Wraps an expression into a script with an entry point for the given stage.
+
+For fragment shaders the expression is the `Vec4` output color, with the `frag_coord: Vec4` input available. For vertex shaders it's the `Vec4` output position, with the `vertex_index: i32` input available. For compute shaders it's evaluated for its side effects, with the `id: UVec3` global invocation id available.
+*/
+pub fn expr_script(expr: &str, stage: &str) -> MainResult<String> {
+    let entry_point = match stage {
+        "fragment" => format!(
+            r#"#[spirv(fragment)]
+pub fn main_fs(#[spirv(frag_coord)] frag_coord: Vec4, output: &mut Vec4) {{
+    *output = {{ {expr} }};
+}}
+"#
+        ),
+        "vertex" => format!(
+            r#"#[spirv(vertex)]
+pub fn main_vs(
+    #[spirv(vertex_index)] vertex_index: i32,
+    #[spirv(position)] position: &mut Vec4,
+) {{
+    *position = {{ {expr} }};
+}}
+"#
+        ),
+        "compute" => format!(
+            r#"#[spirv(compute(threads(1)))]
+pub fn main_cs(#[spirv(global_invocation_id)] id: UVec3) {{
+    let _ = {{ {expr} }};
+}}
+"#
+        ),
+        _ => return Err(format!("unknown shader stage: '{}'", stage).into()),
+    };
+
+    Ok(format!("{PRELUDE}\n{entry_point}"))
+}
+
+/**
+A script to start from for the given stage, as created by `new`: an entry point with an example body to replace.
+*/
+pub fn new_script(stage: &str) -> MainResult<String> {
+    let expr = match stage {
+        "fragment" => "vec4(frag_coord.x / 512.0, frag_coord.y / 512.0, 0.0, 1.0)",
+        // A triangle covering the viewport, for vertex indices 0 to 2.
+        "vertex" => {
+            "vec4(((vertex_index << 1) & 2) as f32 * 2.0 - 1.0, (vertex_index & 2) as f32 * 2.0 - 1.0, 0.0, 1.0)"
+        }
+        "compute" => "id.x",
+        _ => return Err(format!("unknown shader stage: '{}'", stage).into()),
+    };
+    expr_script(expr, stage)
+}
@@ -0,0 +1,49 @@
+/*!
+Helpers shared by the tests of the modules working on SPIR-V.
+*/
+
+use rspirv::binary::Assemble;
+use rspirv::dr::Builder;
+use rspirv::spirv::{
+    AddressingModel, Capability, ExecutionModel, FunctionControl, MemoryModel, Word,
+};
+
+/**
+A builder for a shader module, which declares the `Shader` capability and the logical GLSL450 memory model.
+*/
+pub fn shader_builder() -> Builder {
+    let mut b = Builder::new();
+    b.capability(Capability::Shader);
+    b.memory_model(AddressingModel::Logical, MemoryModel::GLSL450);
+    b
+}
+
+/**
+Add an entry point to the module being built, and return the bytes of the module.
+
+`body` is called with the builder inside the block of the entry point function, and the id of the function. Global variables have to be declared before, as they would otherwise end up in the function.
+*/
+pub fn assemble_with_entry_point(
+    mut b: Builder,
+    execution_model: ExecutionModel,
+    name: &str,
+    interface: &[Word],
+    body: impl FnOnce(&mut Builder, Word),
+) -> Vec<u8> {
+    let void = b.type_void();
+    let fn_type = b.type_function(void, vec![]);
+    let main = b
+        .begin_function(void, None, FunctionControl::NONE, fn_type)
+        .unwrap();
+    b.begin_block(None).unwrap();
+    body(&mut b, main);
+    b.ret().unwrap();
+    b.end_function().unwrap();
+    b.entry_point(execution_model, main, name, interface);
+
+    b.module()
+        .assemble()
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect()
+}
@@ -0,0 +1,165 @@
+/*!
+Translation of SPIR-V modules into WGSL, GLSL and MSL, using naga.
+*/
+
+use naga::back::{glsl, msl, wgsl};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::error::MainResult;
+
+/// The languages a module can be translated into, as given to `--emit`.
+pub const LANGUAGES: &[&str] = &["wgsl", "glsl", "msl"];
+
+/**
+A module translated into another language.
+*/
+pub struct Translation {
+    /// The entry point this translation is for, for languages which only allow one entry point per file.
+    pub entry_point: Option<String>,
+
+    /// The translated source.
+    pub source: String,
+}
+
+// Describe an error including its causes, as naga errors keep the details in their sources.
+fn describe_error(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message += &format!(": {}", err);
+        source = err.source();
+    }
+    message
+}
+
+/**
+Translate the given SPIR-V module into the given language, one of `LANGUAGES`.
+
+GLSL only allows one entry point per shader, so a translation is returned for each entry point in that case.
+*/
+pub fn translate(spirv: &[u8], language: &str) -> MainResult<Vec<Translation>> {
+    let module = naga::front::spv::parse_u8_slice(spirv, &Default::default())
+        .map_err(|e| format!("could not read SPIR-V module: {}", describe_error(&e)))?;
+
+    // WGSL, unlike GLSL and MSL, can only express the features of WebGPU, and push constants as supported by wgpu.
+    let capabilities = match language {
+        "wgsl" => Capabilities::default() | Capabilities::PUSH_CONSTANT,
+        _ => Capabilities::all(),
+    };
+    let info = Validator::new(ValidationFlags::all(), capabilities)
+        .validate(&module)
+        .map_err(|e| {
+            format!(
+                "module cannot be expressed in {}: {}",
+                language.to_uppercase(),
+                describe_error(&e)
+            )
+        })?;
+
+    let translation_error = |e: &dyn std::error::Error| {
+        format!(
+            "could not translate module to {}: {}",
+            language.to_uppercase(),
+            describe_error(e)
+        )
+    };
+
+    match language {
+        "wgsl" => {
+            let source = wgsl::write_string(&module, &info, wgsl::WriterFlags::empty())
+                .map_err(|e| translation_error(&e))?;
+            Ok(vec![Translation {
+                entry_point: None,
+                source,
+            }])
+        }
+        "msl" => {
+            let (source, _) = msl::write_string(
+                &module,
+                &info,
+                &msl::Options::default(),
+                &msl::PipelineOptions::default(),
+            )
+            .map_err(|e| translation_error(&e))?;
+            Ok(vec![Translation {
+                entry_point: None,
+                source,
+            }])
+        }
+        "glsl" => {
+            let options = glsl::Options {
+                version: glsl::Version::Desktop(450),
+                ..Default::default()
+            };
+            let mut translations = Vec::new();
+            for entry_point in &module.entry_points {
+                let pipeline_options = glsl::PipelineOptions {
+                    shader_stage: entry_point.stage,
+                    entry_point: entry_point.name.clone(),
+                    multiview: None,
+                };
+                let mut source = String::new();
+                let mut writer = glsl::Writer::new(
+                    &mut source,
+                    &module,
+                    &info,
+                    &options,
+                    &pipeline_options,
+                    Default::default(),
+                )
+                .map_err(|e| translation_error(&e))?;
+                writer.write().map_err(|e| translation_error(&e))?;
+                translations.push(Translation {
+                    entry_point: Some(entry_point.name.clone()),
+                    source,
+                });
+            }
+            if translations.is_empty() {
+                return Err("module has no entry points to translate to GLSL".into());
+            }
+            Ok(translations)
+        }
+        _ => Err(format!("unknown language: {}", language).into()),
+    }
+}
+
+#[test]
+fn test_translate() {
+    use crate::test_util::{assemble_with_entry_point, shader_builder};
+    use rspirv::dr::Operand;
+    use rspirv::spirv::{Decoration, ExecutionMode, ExecutionModel, StorageClass};
+
+    let mut b = shader_builder();
+    let float = b.type_float(32);
+    let v4float = b.type_vector(float, 4);
+    let output_ptr = b.type_pointer(None, StorageClass::Output, v4float);
+    let output = b.variable(output_ptr, None, StorageClass::Output, None);
+    b.decorate(output, Decoration::Location, [Operand::LiteralInt32(0)]);
+    let one = b.constant_f32(float, 1.0);
+    let red = b.constant_composite(v4float, [one, one, one, one]);
+    let spirv = assemble_with_entry_point(
+        b,
+        ExecutionModel::Fragment,
+        "main_fs",
+        &[output],
+        |b, main| {
+            b.store(output, red, None, []).unwrap();
+            b.execution_mode(main, ExecutionMode::OriginUpperLeft, []);
+        },
+    );
+
+    let wgsl = translate(&spirv, "wgsl").unwrap();
+    assert_eq!(wgsl.len(), 1);
+    assert!(wgsl[0].source.contains("@fragment"));
+
+    let glsl = translate(&spirv, "glsl").unwrap();
+    assert_eq!(glsl.len(), 1);
+    assert_eq!(glsl[0].entry_point.as_deref(), Some("main_fs"));
+    assert!(glsl[0].source.starts_with("#version 450"));
+
+    assert!(translate(&spirv, "msl").unwrap()[0]
+        .source
+        .contains("fragment"));
+
+    assert!(translate(b"not spir-v", "wgsl").is_err());
+}
@@ -0,0 +1,496 @@
+/*!
+Validation of built modules against the target environment.
+
+This checks that the capabilities a module declares are supported by the environment, which is the common way in which a module that builds is still rejected when creating a pipeline. The structure of the module isn't checked, which is what `spirv-val` is for.
+*/
+
+use rspirv::dr::{Module, Operand};
+use rspirv::spirv::{Capability, Op, Word};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::disasm::Disassembler;
+use crate::error::MainResult;
+
+/// Capabilities which a Vulkan 1.0 implementation can support, either in core or through an extension.
+const VULKAN_CAPABILITIES: &[Capability] = &[
+    Capability::Matrix,
+    Capability::Shader,
+    Capability::InputAttachment,
+    Capability::Sampled1D,
+    Capability::Image1D,
+    Capability::SampledBuffer,
+    Capability::ImageBuffer,
+    Capability::ImageQuery,
+    Capability::DerivativeControl,
+    Capability::Geometry,
+    Capability::Tessellation,
+    Capability::Float16,
+    Capability::Float64,
+    Capability::Int8,
+    Capability::Int16,
+    Capability::Int64,
+    Capability::Int64Atomics,
+    Capability::TessellationPointSize,
+    Capability::GeometryPointSize,
+    Capability::ImageGatherExtended,
+    Capability::StorageImageMultisample,
+    Capability::UniformBufferArrayDynamicIndexing,
+    Capability::SampledImageArrayDynamicIndexing,
+    Capability::StorageBufferArrayDynamicIndexing,
+    Capability::StorageImageArrayDynamicIndexing,
+    Capability::ClipDistance,
+    Capability::CullDistance,
+    Capability::ImageCubeArray,
+    Capability::SampleRateShading,
+    Capability::SparseResidency,
+    Capability::MinLod,
+    Capability::SampledCubeArray,
+    Capability::ImageMSArray,
+    Capability::StorageImageExtendedFormats,
+    Capability::InterpolationFunction,
+    Capability::StorageImageReadWithoutFormat,
+    Capability::StorageImageWriteWithoutFormat,
+    Capability::MultiViewport,
+    Capability::TransformFeedback,
+    Capability::GeometryStreams,
+    Capability::DrawParameters,
+    Capability::MultiView,
+    Capability::DeviceGroup,
+    Capability::VariablePointersStorageBuffer,
+    Capability::VariablePointers,
+    Capability::SubgroupBallotKHR,
+    Capability::SubgroupVoteKHR,
+    Capability::StorageBuffer16BitAccess,
+    Capability::UniformAndStorageBuffer16BitAccess,
+    Capability::StoragePushConstant16,
+    Capability::StorageInputOutput16,
+    Capability::StorageBuffer8BitAccess,
+    Capability::UniformAndStorageBuffer8BitAccess,
+    Capability::StoragePushConstant8,
+    Capability::DenormPreserve,
+    Capability::DenormFlushToZero,
+    Capability::SignedZeroInfNanPreserve,
+    Capability::RoundingModeRTE,
+    Capability::RoundingModeRTZ,
+    Capability::ShaderNonUniform,
+    Capability::RuntimeDescriptorArray,
+    Capability::InputAttachmentArrayDynamicIndexing,
+    Capability::UniformTexelBufferArrayDynamicIndexing,
+    Capability::StorageTexelBufferArrayDynamicIndexing,
+    Capability::UniformBufferArrayNonUniformIndexing,
+    Capability::SampledImageArrayNonUniformIndexing,
+    Capability::StorageBufferArrayNonUniformIndexing,
+    Capability::StorageImageArrayNonUniformIndexing,
+    Capability::InputAttachmentArrayNonUniformIndexing,
+    Capability::UniformTexelBufferArrayNonUniformIndexing,
+    Capability::StorageTexelBufferArrayNonUniformIndexing,
+    Capability::ShaderViewportIndexLayerEXT,
+    Capability::VulkanMemoryModel,
+    Capability::VulkanMemoryModelDeviceScope,
+    Capability::PhysicalStorageBufferAddresses,
+    Capability::SampleMaskPostDepthCoverage,
+    Capability::StencilExportEXT,
+    Capability::ShaderClockKHR,
+    Capability::FragmentShadingRateKHR,
+    Capability::FragmentDensityEXT,
+    Capability::FragmentFullyCoveredEXT,
+    Capability::FragmentBarycentricNV,
+    Capability::FragmentShaderSampleInterlockEXT,
+    Capability::FragmentShaderPixelInterlockEXT,
+    Capability::FragmentShaderShadingRateInterlockEXT,
+    Capability::DemoteToHelperInvocationEXT,
+    Capability::Int64ImageEXT,
+    Capability::AtomicFloat32AddEXT,
+    Capability::AtomicFloat64AddEXT,
+    Capability::RayTracingNV,
+    Capability::MeshShadingNV,
+    Capability::ImageFootprintNV,
+    Capability::ComputeDerivativeGroupQuadsNV,
+    Capability::ComputeDerivativeGroupLinearNV,
+    Capability::CooperativeMatrixNV,
+    Capability::SampleMaskOverrideCoverageNV,
+    Capability::GeometryShaderPassthroughNV,
+    Capability::ShaderViewportMaskNV,
+    Capability::ShaderStereoViewNV,
+    Capability::PerViewAttributesNV,
+    Capability::Float16ImageAMD,
+    Capability::ImageGatherBiasLodAMD,
+    Capability::FragmentMaskAMD,
+    Capability::ImageReadWriteLodAMD,
+    Capability::IntegerFunctions2INTEL,
+];
+
+/// Capabilities which additionally need Vulkan 1.1, as they are core in it or only provided by extensions which require it.
+const VULKAN_1_1_CAPABILITIES: &[Capability] = &[
+    Capability::GroupNonUniform,
+    Capability::GroupNonUniformVote,
+    Capability::GroupNonUniformArithmetic,
+    Capability::GroupNonUniformBallot,
+    Capability::GroupNonUniformShuffle,
+    Capability::GroupNonUniformShuffleRelative,
+    Capability::GroupNonUniformClustered,
+    Capability::GroupNonUniformQuad,
+    Capability::GroupNonUniformPartitionedNV,
+    Capability::RayQueryKHR,
+    Capability::RayTracingKHR,
+    Capability::RayTraversalPrimitiveCullingKHR,
+    Capability::ShaderSMBuiltinsNV,
+];
+
+/// Capabilities which additionally need Vulkan 1.2, as they are core in it and not provided by any extension.
+const VULKAN_1_2_CAPABILITIES: &[Capability] =
+    &[Capability::ShaderLayer, Capability::ShaderViewportIndex];
+
+/// Capabilities which an OpenGL 4 implementation can support, with `GL_ARB_gl_spirv` and extensions.
+const OPENGL_CAPABILITIES: &[Capability] = &[
+    Capability::Matrix,
+    Capability::Shader,
+    Capability::Geometry,
+    Capability::Tessellation,
+    Capability::Float64,
+    Capability::Int64,
+    Capability::AtomicStorage,
+    Capability::TessellationPointSize,
+    Capability::GeometryPointSize,
+    Capability::ImageGatherExtended,
+    Capability::StorageImageMultisample,
+    Capability::UniformBufferArrayDynamicIndexing,
+    Capability::SampledImageArrayDynamicIndexing,
+    Capability::StorageBufferArrayDynamicIndexing,
+    Capability::StorageImageArrayDynamicIndexing,
+    Capability::ClipDistance,
+    Capability::CullDistance,
+    Capability::ImageCubeArray,
+    Capability::SampleRateShading,
+    Capability::ImageRect,
+    Capability::SampledRect,
+    Capability::Sampled1D,
+    Capability::Image1D,
+    Capability::SampledCubeArray,
+    Capability::SampledBuffer,
+    Capability::ImageBuffer,
+    Capability::ImageMSArray,
+    Capability::StorageImageExtendedFormats,
+    Capability::ImageQuery,
+    Capability::DerivativeControl,
+    Capability::InterpolationFunction,
+    Capability::TransformFeedback,
+    Capability::GeometryStreams,
+    Capability::StorageImageReadWithoutFormat,
+    Capability::StorageImageWriteWithoutFormat,
+    Capability::MultiViewport,
+    Capability::DrawParameters,
+    Capability::SubgroupBallotKHR,
+    Capability::SubgroupVoteKHR,
+    Capability::ShaderViewportIndexLayerEXT,
+];
+
+/// Capabilities allowed by WebGPU.
+const WEBGPU_CAPABILITIES: &[Capability] = &[
+    Capability::Matrix,
+    Capability::Shader,
+    Capability::Sampled1D,
+    Capability::Image1D,
+    Capability::DerivativeControl,
+    Capability::ImageQuery,
+    Capability::VulkanMemoryModel,
+];
+
+/**
+The capabilities supported by a target environment.
+*/
+struct TargetEnv {
+    /// The environment part of the target, such as `vulkan1.1`.
+    name: String,
+
+    /// The tables of capabilities modules may declare, or `None` if any capability is allowed.
+    capabilities: Option<&'static [&'static [Capability]]>,
+}
+
+// The capabilities of each Vulkan version, as those it adds to the previous one.
+const VULKAN_1_0: &[&[Capability]] = &[VULKAN_CAPABILITIES];
+const VULKAN_1_1: &[&[Capability]] = &[VULKAN_CAPABILITIES, VULKAN_1_1_CAPABILITIES];
+const VULKAN_1_2: &[&[Capability]] = &[
+    VULKAN_CAPABILITIES,
+    VULKAN_1_1_CAPABILITIES,
+    VULKAN_1_2_CAPABILITIES,
+];
+
+impl TargetEnv {
+    fn parse(target: &str) -> MainResult<Self> {
+        let name = target.strip_prefix("spirv-unknown-").unwrap_or(target);
+        let capabilities = match name {
+            "vulkan1.0" => Some(VULKAN_1_0),
+            "vulkan1.1" | "vulkan1.1spv1.4" => Some(VULKAN_1_1),
+            "vulkan1.2" => Some(VULKAN_1_2),
+            "webgpu0" => Some(&[WEBGPU_CAPABILITIES][..]),
+            _ if name.starts_with("opengl4.") => Some(&[OPENGL_CAPABILITIES][..]),
+            // Plain SPIR-V targets don't restrict the capabilities.
+            _ if name.starts_with("spv1.") => None,
+            _ => return Err(format!("cannot validate for unknown target: {}", target).into()),
+        };
+        Ok(Self {
+            name: name.into(),
+            capabilities,
+        })
+    }
+
+    fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.map_or(true, |tables| {
+            tables
+                .iter()
+                .any(|capabilities| capabilities.contains(&capability))
+        })
+    }
+}
+
+/**
+A way in which a module is not valid for the target environment.
+*/
+#[derive(Debug)]
+pub struct Violation {
+    pub message: String,
+
+    /// The entry points which use the offending instruction.
+    pub entry_points: Vec<String>,
+
+    /// The offending instruction, disassembled.
+    pub instruction: Option<String>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.entry_points.is_empty() {
+            write!(f, "\n    in entry point {}", self.entry_points.join(", "))?;
+        }
+        if let Some(instruction) = &self.instruction {
+            write!(f, "\n    {}", instruction.trim())?;
+        }
+        Ok(())
+    }
+}
+
+/**
+Validate the given SPIR-V module for the given `--target`, returning the violations found.
+
+Each capability the environment doesn't support is reported with the entry points using instructions which need it.
+*/
+pub fn validate(spirv: &[u8], target: &str) -> MainResult<Vec<Violation>> {
+    let env = TargetEnv::parse(target)?;
+    let module = rspirv::dr::load_bytes(spirv)
+        .map_err(|e| format!("could not parse SPIR-V module: {}", e))?;
+    let disassembler = Disassembler::new(&module);
+    let function_entry_points = function_entry_points(&module);
+
+    let mut violations = Vec::new();
+    for inst in &module.capabilities {
+        let Some(Operand::Capability(capability)) = inst.operands.first() else {
+            continue;
+        };
+        if env.allows(*capability) {
+            continue;
+        }
+        let mut entry_points: Vec<String> = module
+            .functions
+            .iter()
+            .filter(|function| {
+                function
+                    .all_inst_iter()
+                    .any(|inst| inst.class.capabilities.contains(capability))
+            })
+            .filter_map(|function| function_entry_points.get(&function.def_id()?))
+            .flatten()
+            .cloned()
+            .collect();
+        entry_points.sort();
+        entry_points.dedup();
+        violations.push(Violation {
+            message: format!(
+                "capability {:?} is not supported by {}",
+                capability, env.name
+            ),
+            entry_points,
+            instruction: Some(disassembler.instruction(inst)),
+        });
+    }
+    Ok(violations)
+}
+
+/**
+Find the entry points from which each function can be reached through calls.
+*/
+fn function_entry_points(module: &Module) -> HashMap<Word, Vec<String>> {
+    let mut calls: HashMap<Word, Vec<Word>> = HashMap::new();
+    for function in &module.functions {
+        let Some(id) = function.def_id() else {
+            continue;
+        };
+        let callees = function
+            .all_inst_iter()
+            .filter(|inst| inst.class.opcode == Op::FunctionCall)
+            .filter_map(|inst| match inst.operands.first() {
+                Some(Operand::IdRef(callee)) => Some(*callee),
+                _ => None,
+            });
+        calls.entry(id).or_default().extend(callees);
+    }
+
+    let mut function_entry_points: HashMap<Word, Vec<String>> = HashMap::new();
+    for inst in &module.entry_points {
+        let (Some(Operand::IdRef(function)), Some(Operand::LiteralString(name))) =
+            (inst.operands.get(1), inst.operands.get(2))
+        else {
+            continue;
+        };
+        let mut pending = vec![*function];
+        let mut visited = HashSet::new();
+        while let Some(function) = pending.pop() {
+            if !visited.insert(function) {
+                continue;
+            }
+            function_entry_points
+                .entry(function)
+                .or_default()
+                .push(name.clone());
+            pending.extend(calls.get(&function).into_iter().flatten());
+        }
+    }
+    function_entry_points
+}
+
+// A compute shader declaring the given capabilities besides `Shader`, with a group operation if it declares `GroupNonUniform`.
+#[cfg(test)]
+fn test_module(capabilities: &[Capability]) -> Vec<u8> {
+    use crate::test_util::{assemble_with_entry_point, shader_builder};
+    use rspirv::spirv::{ExecutionModel, Scope};
+
+    let mut b = shader_builder();
+    b.set_version(1, 3);
+    for capability in capabilities {
+        b.capability(*capability);
+    }
+    let bool_type = b.type_bool();
+    let uint = b.type_int(32, 0);
+    let subgroup = b.constant_u32(uint, Scope::Subgroup as u32);
+    let group_operation = capabilities.contains(&Capability::GroupNonUniform);
+    assemble_with_entry_point(b, ExecutionModel::GLCompute, "main_cs", &[], |b, _| {
+        if group_operation {
+            b.group_non_uniform_elect(bool_type, None, subgroup)
+                .unwrap();
+        }
+    })
+}
+
+#[test]
+fn test_vulkan_1_0_capabilities() {
+    let module = test_module(&[Capability::Float64, Capability::SubgroupBallotKHR]);
+    assert!(validate(&module, "spirv-unknown-vulkan1.0")
+        .unwrap()
+        .is_empty());
+
+    // Group operations need Vulkan 1.1, and the violation names the entry point using them.
+    let violations = validate(
+        &test_module(&[Capability::GroupNonUniform]),
+        "spirv-unknown-vulkan1.0",
+    )
+    .unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(
+        violations[0].to_string(),
+        "capability GroupNonUniform is not supported by vulkan1.0\n    in entry point main_cs\n    OpCapability GroupNonUniform"
+    );
+
+    assert!(validate(&module, "spirv-unknown-vulkan2.0").is_err());
+}
+
+#[test]
+fn test_vulkan_1_1_capabilities() {
+    let module = test_module(&[Capability::GroupNonUniform, Capability::Float64]);
+    assert!(validate(&module, "spirv-unknown-vulkan1.1")
+        .unwrap()
+        .is_empty());
+    assert!(validate(&module, "spirv-unknown-vulkan1.1spv1.4")
+        .unwrap()
+        .is_empty());
+
+    let violations = validate(
+        &test_module(&[Capability::ShaderLayer]),
+        "spirv-unknown-vulkan1.1",
+    )
+    .unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].entry_points, Vec::<String>::new());
+}
+
+#[test]
+fn test_vulkan_1_2_capabilities() {
+    let module = test_module(&[
+        Capability::ShaderLayer,
+        Capability::GroupNonUniform,
+        Capability::Float64,
+    ]);
+    assert!(validate(&module, "spirv-unknown-vulkan1.2")
+        .unwrap()
+        .is_empty());
+
+    let violations = validate(
+        &test_module(&[Capability::Kernel]),
+        "spirv-unknown-vulkan1.2",
+    )
+    .unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(
+        violations[0].message,
+        "capability Kernel is not supported by vulkan1.2"
+    );
+}
+
+#[test]
+fn test_opengl_capabilities() {
+    let module = test_module(&[Capability::AtomicStorage, Capability::ImageRect]);
+    assert!(validate(&module, "spirv-unknown-opengl4.5")
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        validate(&module, "spirv-unknown-vulkan1.2").unwrap().len(),
+        2
+    );
+
+    let violations = validate(
+        &test_module(&[Capability::GroupNonUniform]),
+        "spirv-unknown-opengl4.5",
+    )
+    .unwrap();
+    assert_eq!(violations.len(), 1);
+}
+
+#[test]
+fn test_webgpu_capabilities() {
+    let module = test_module(&[Capability::VulkanMemoryModel]);
+    assert!(validate(&module, "spirv-unknown-webgpu0")
+        .unwrap()
+        .is_empty());
+
+    let violations = validate(
+        &test_module(&[Capability::Int64, Capability::Float64]),
+        "spirv-unknown-webgpu0",
+    )
+    .unwrap();
+    let messages: Vec<&str> = violations.iter().map(|v| v.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "capability Int64 is not supported by webgpu0",
+            "capability Float64 is not supported by webgpu0"
+        ]
+    );
+
+    // Plain SPIR-V targets allow any capability.
+    assert!(
+        validate(&test_module(&[Capability::Int64]), "spirv-unknown-spv1.3")
+            .unwrap()
+            .is_empty()
+    );
+}
@@ -0,0 +1,37 @@
+/*!
+Polling based file watching, used by `--watch`.
+*/
+
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::consts;
+
+// Last-modified time of a file, or `None` if it does not exist.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|md| md.modified()).ok()
+}
+
+/**
+Blocks until one of the given files is modified, created or removed.
+*/
+pub fn wait_for_change(paths: &[PathBuf]) {
+    let snapshot: HashMap<&Path, Option<SystemTime>> = paths
+        .iter()
+        .map(|path| (path.as_path(), modified_time(path)))
+        .collect();
+
+    loop {
+        std::thread::sleep(Duration::from_millis(consts::WATCH_POLL_INTERVAL_MS));
+
+        for (path, modified) in &snapshot {
+            if modified_time(path) != *modified {
+                info!("change detected in {:?}", path);
+                return;
+            }
+        }
+    }
+}